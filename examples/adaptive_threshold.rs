@@ -0,0 +1,22 @@
+use futures::executor::block_on;
+use wgimage::*;
+
+fn main() {
+    let context = WgContext::new();
+    let context = block_on(context).unwrap();
+    let image = image::open("examples/lenna_grayscale.png")
+        .unwrap()
+        .to_rgba8();
+    let image_buffer = WgImageBuffer::from_host_image(&context, image).unwrap();
+    let mut threshold =
+        AdaptiveThreshold::new(&context, AdaptiveMethod::Gaussian, 11, 2.0).unwrap();
+    threshold.run(&image_buffer).unwrap();
+    let threshold_image = threshold
+        .output_image()
+        .unwrap()
+        .to_host_image::<image::Rgba<u8>>(&context);
+    threshold_image
+        .unwrap()
+        .save("examples/lenna_adaptive_threshold.png")
+        .unwrap();
+}
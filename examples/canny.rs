@@ -0,0 +1,19 @@
+use futures::executor::block_on;
+use wgimage::*;
+
+fn main() {
+    let context = WgContext::new();
+    let context = block_on(context).unwrap();
+    let image = image::open("examples/lenna.png").unwrap().to_rgba8();
+    let image_buffer = WgImageBuffer::from_host_image(&context, image).unwrap();
+    let mut canny = Canny::new(&context, 1.4, 50.0, 100.0).unwrap();
+    canny.run(&image_buffer).unwrap();
+    let edges_image = canny
+        .output_image()
+        .unwrap()
+        .to_host_image::<image::Rgba<u8>>(&context);
+    edges_image
+        .unwrap()
+        .save("examples/lenna_canny.png")
+        .unwrap();
+}
@@ -0,0 +1,24 @@
+use futures::executor::block_on;
+use wgimage::*;
+
+fn main() {
+    let context = WgContext::new();
+    let context = block_on(context).unwrap();
+    let image = image::open("examples/lenna.png").unwrap().to_rgba8();
+    let image_buffer = WgImageBuffer::from_host_image(&context, image).unwrap();
+    let mut color_convert = ColorConvert::new(
+        &context,
+        ColorSpace::Rgb,
+        ColorSpace::YCbCr(YCbCrMatrix::Bt601, ColorRange::Full),
+    )
+    .unwrap();
+    color_convert.run(&image_buffer).unwrap();
+    let ycbcr_image = color_convert
+        .output_image()
+        .unwrap()
+        .to_host_image::<image::Rgba<u8>>(&context);
+    ycbcr_image
+        .unwrap()
+        .save("examples/lenna_ycbcr.png")
+        .unwrap();
+}
@@ -0,0 +1,21 @@
+use futures::executor::block_on;
+use wgimage::*;
+
+const SHARPEN: [f32; 9] = [0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0];
+
+fn main() {
+    let context = WgContext::new();
+    let context = block_on(context).unwrap();
+    let image = image::open("examples/lenna.png").unwrap().to_rgba8();
+    let image_buffer = WgImageBuffer::from_host_image(&context, image).unwrap();
+    let mut sharpen = Convolve2D::new(&context, &SHARPEN, 3, 3).unwrap();
+    sharpen.run(&image_buffer).unwrap();
+    let sharpen_image = sharpen
+        .output_image()
+        .unwrap()
+        .to_host_image::<image::Rgba<u8>>(&context);
+    sharpen_image
+        .unwrap()
+        .save("examples/lenna_sharpen.png")
+        .unwrap();
+}
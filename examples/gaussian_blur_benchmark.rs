@@ -0,0 +1,38 @@
+use std::time::Instant;
+
+use futures::executor::block_on;
+use wgimage::*;
+
+const ITERATIONS: u32 = 10;
+
+fn main() {
+    let context = WgContext::new();
+    let context = block_on(context).unwrap();
+    let image = image::open("examples/lenna.png").unwrap().to_rgba8();
+    let image_buffer = WgImageBuffer::from_host_image(&context, image).unwrap();
+    println!("sigma  kernel  direct (ms)  tiled (ms)  speedup");
+    for sigma in [1.0, 2.0, 3.0, 5.0, 10.0, 20.0] {
+        let mut gaussian_blur = GaussianBlur::new(&context, sigma).unwrap();
+        let mut time = |method| {
+            gaussian_blur.set_method(method);
+            gaussian_blur.run(&image_buffer).unwrap();
+            context.wait();
+            let start = Instant::now();
+            for _ in 0..ITERATIONS {
+                gaussian_blur.run(&image_buffer).unwrap();
+            }
+            context.wait();
+            start.elapsed().as_secs_f64() * 1000.0 / ITERATIONS as f64
+        };
+        let direct = time(ConvolutionMethod::Direct);
+        let tiled = time(ConvolutionMethod::Tiled);
+        println!(
+            "{:>5}  {:>6}  {:>11.2}  {:>10.2}  {:>6.2}x",
+            sigma,
+            gaussian_blur.kernel_size().0,
+            direct,
+            tiled,
+            direct / tiled
+        );
+    }
+}
@@ -0,0 +1,21 @@
+use futures::executor::block_on;
+use wgimage::*;
+
+fn main() {
+    let context = WgContext::new();
+    let context = block_on(context).unwrap();
+    let image = image::open("examples/lenna.png").unwrap().to_rgba8();
+    let image_buffer = WgImageBuffer::from_host_image(&context, image).unwrap();
+    let mut gradient = Gradient::new(&context, GradientOperator::Sobel3).unwrap();
+    gradient.set_normalization(GradientNormalization::Rgba8);
+    gradient.set_magnitude_output(true);
+    gradient.run(&image_buffer).unwrap();
+    let magnitude_image = gradient
+        .magnitude_image()
+        .unwrap()
+        .to_host_image::<image::Rgba<u8>>(&context);
+    magnitude_image
+        .unwrap()
+        .save("examples/lenna_gradient.png")
+        .unwrap();
+}
@@ -3,14 +3,15 @@ use wgimage::*;
 
 fn main() {
     let context = WgContext::new();
-    let context = block_on(context);
+    let context = block_on(context).unwrap();
     let image = image::open("examples/lenna.png").unwrap().to_rgba8();
-    let width = image.width();
-    let height = image.height();
-    let image_buffer = WgImageBuffer::from_host_image(&context, image);
-    let mut grayscale = GrayScale::new(&context, width, height);
-    grayscale.run(&image_buffer);
-    let grayscale_image = grayscale.output_image.to_host_image(&context);
+    let image_buffer = WgImageBuffer::from_host_image(&context, image).unwrap();
+    let mut grayscale = GrayScale::new(&context).unwrap();
+    grayscale.run(&image_buffer).unwrap();
+    let grayscale_image = grayscale
+        .output_image()
+        .unwrap()
+        .to_host_image::<image::Rgba<u8>>(&context);
     grayscale_image
         .unwrap()
         .save("examples/lenna_grayscale.png")
@@ -0,0 +1,22 @@
+use futures::executor::block_on;
+use wgimage::*;
+
+fn main() {
+    let context = WgContext::new();
+    let context = block_on(context).unwrap();
+    let image = image::open("examples/lenna.png").unwrap().to_rgba8();
+    let image_buffer = WgImageBuffer::from_host_image(&context, image).unwrap();
+    let mut pipeline = Pipeline::new(&context, &image_buffer)
+        .then(GrayScale::new(&context).unwrap())
+        .then(GaussianBlur::new(&context, 2.0).unwrap())
+        .then(Threshold::new(&context, 128).unwrap());
+    pipeline.run().unwrap();
+    let pipeline_image = pipeline
+        .output_image()
+        .unwrap()
+        .to_host_image::<image::Rgba<u8>>(&context);
+    pipeline_image
+        .unwrap()
+        .save("examples/lenna_pipeline.png")
+        .unwrap();
+}
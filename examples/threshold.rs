@@ -3,16 +3,17 @@ use wgimage::*;
 
 fn main() {
     let context = WgContext::new();
-    let context = block_on(context);
+    let context = block_on(context).unwrap();
     let image = image::open("examples/lenna_grayscale.png")
         .unwrap()
         .to_rgba8();
-    let width = image.width();
-    let height = image.height();
-    let image_buffer = WgImageBuffer::from_host_image(&context, image);
-    let mut threshold = Threshold::new(&context, width, height, 128);
-    threshold.run(&image_buffer);
-    let grayscale_image = threshold.output_image.to_host_image(&context);
+    let image_buffer = WgImageBuffer::from_host_image(&context, image).unwrap();
+    let mut threshold = Threshold::new(&context, 128).unwrap();
+    threshold.run(&image_buffer).unwrap();
+    let grayscale_image = threshold
+        .output_image()
+        .unwrap()
+        .to_host_image::<image::Rgba<u8>>(&context);
     grayscale_image
         .unwrap()
         .save("examples/lenna_threshold.png")
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+use wgpu::{
+    util::BufferInitDescriptor, BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource,
+    Buffer, BufferUsages, CommandEncoder, ComputePipeline, TextureFormat,
+};
+
+use super::border::BorderMode;
+use super::buffer::WgImageBuffer;
+use super::context::WgContext;
+use super::error::Result;
+use super::filter::{
+    common_size, descriptors, has_color_channels, luma_weights, single_channel_format,
+    BindGroupCache, Filter, ImageCache, ImageDescriptor,
+};
+use super::gaussian_blur::create_kernel;
+use super::kernel::{check_kernel_size, Kernel};
+use super::separable_convolve::SeparableConvolve;
+use super::threshold::{ThresholdMode, THRESHOLD_MODE_SHADER};
+use super::utils::{
+    compute_work_group_count, shader_with_output_format, storage_texture_entry, texture_entry,
+    uniform_entry,
+};
+
+const ADAPTIVE_THRESHOLD_SHADER: &str = include_str!("shaders/adaptive_threshold.wgsl");
+
+/// Local mean each pixel of `AdaptiveThreshold` is compared against.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AdaptiveMethod {
+    /// Mean of the block around the pixel.
+    #[default]
+    Mean,
+    /// Gaussian-weighted mean of the block, with the sigma derived from the
+    /// block size like `GaussianBlur` does for a zero sigma.
+    Gaussian,
+}
+
+impl AdaptiveMethod {
+    fn kernel(self, block_size: u32) -> Kernel {
+        match self {
+            AdaptiveMethod::Mean => Kernel::normalized(vec![1.0; block_size as usize]),
+            AdaptiveMethod::Gaussian => create_kernel(0.0, block_size),
+        }
+    }
+}
+
+/// Thresholds every pixel against the mean of its neighborhood minus a
+/// constant C, like OpenCV's `adaptiveThreshold`, so that unevenly lit
+/// images keep their details.
+///
+/// Outputs follow the `ThresholdMode` and luminance mask options of
+/// `Threshold`.
+pub struct AdaptiveThreshold {
+    context: WgContext,
+    format: Option<TextureFormat>,
+    /// Local means, kept in `Rgba32Float` to compare against their exact
+    /// value.
+    mean: SeparableConvolve,
+    /// Pipelines by output format and whether the input has color channels.
+    pipelines: HashMap<(TextureFormat, bool), Arc<ComputePipeline>>,
+    images: ImageCache,
+    /// Settings bind group of each pipeline.
+    constants: HashMap<(TextureFormat, bool), BindGroup>,
+    bind_groups: BindGroupCache,
+    settings: Buffer,
+    method: AdaptiveMethod,
+    block_size: u32,
+    constant: f32,
+    max_value: u32,
+    mode: ThresholdMode,
+    luminance_mask: bool,
+}
+
+impl AdaptiveThreshold {
+    /// Writes outputs in the input format when possible, see `with_format`.
+    ///
+    /// `block_size` is the odd width of the neighborhood, and `constant` is
+    /// subtracted from its mean on a 0 to 255 scale.
+    pub fn new(
+        context: &WgContext,
+        method: AdaptiveMethod,
+        block_size: u32,
+        constant: f32,
+    ) -> Result<Self> {
+        check_kernel_size(block_size as usize)?;
+        let settings = context.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Image info"),
+            contents: &[0; 16],
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let kernel = method.kernel(block_size);
+        let mut mean = SeparableConvolve::from_kernels(context, &kernel, &kernel);
+        mean.set_format(TextureFormat::Rgba32Float)?;
+        mean.set_border_mode(BorderMode::Replicate);
+        let filter = AdaptiveThreshold {
+            context: context.clone(),
+            format: None,
+            mean,
+            pipelines: HashMap::new(),
+            images: ImageCache::default(),
+            constants: HashMap::new(),
+            bind_groups: BindGroupCache::default(),
+            settings,
+            method,
+            block_size,
+            constant,
+            max_value: 255,
+            mode: ThresholdMode::default(),
+            luminance_mask: false,
+        };
+        filter.write_settings();
+        Ok(filter)
+    }
+    /// Always writes outputs of `format`.
+    pub fn with_format(
+        context: &WgContext,
+        method: AdaptiveMethod,
+        block_size: u32,
+        constant: f32,
+        format: TextureFormat,
+    ) -> Result<Self> {
+        let mut filter = Self::new(context, method, block_size, constant)?;
+        filter.format = Some(format);
+        filter.pipeline(format, true)?;
+        Ok(filter)
+    }
+    fn pipeline(&mut self, format: TextureFormat, color: bool) -> Result<&ComputePipeline> {
+        let key = (format, color);
+        if !self.pipelines.contains_key(&key) {
+            let source = format!("{}\n{}", THRESHOLD_MODE_SHADER, ADAPTIVE_THRESHOLD_SHADER)
+                .replace("LUMA_WEIGHTS", luma_weights(color));
+            let shader = shader_with_output_format(&self.context, &source, format)?;
+            let pipeline = self.context.create_compute_pipeline(
+                "adaptive threshold",
+                &shader,
+                "main",
+                &[
+                    &[uniform_entry(0)],
+                    &[
+                        texture_entry(0),
+                        texture_entry(1),
+                        storage_texture_entry(2, format),
+                    ],
+                ],
+            )?;
+            self.pipelines.insert(key, pipeline);
+        }
+        Ok(&self.pipelines[&key])
+    }
+    fn write_settings(&self) {
+        let settings = [
+            f32::to_bits(self.max_value as f32 / 255.0),
+            self.mode.shader_index(),
+            self.luminance_mask as u32,
+            f32::to_bits(self.constant / 255.0),
+        ];
+        self.context
+            .queue
+            .write_buffer(&self.settings, 0, bytemuck::cast_slice(&settings));
+    }
+    fn update_kernel(&mut self) {
+        let kernel = self.method.kernel(self.block_size);
+        self.mean.set_packed_kernels(&kernel, &kernel);
+    }
+    pub fn method(&self) -> AdaptiveMethod {
+        self.method
+    }
+    pub fn set_method(&mut self, method: AdaptiveMethod) {
+        self.method = method;
+        self.update_kernel();
+    }
+    pub fn block_size(&self) -> u32 {
+        self.block_size
+    }
+    /// Changes the neighborhood width, which must be odd.
+    pub fn set_block_size(&mut self, block_size: u32) -> Result<()> {
+        check_kernel_size(block_size as usize)?;
+        self.block_size = block_size;
+        self.update_kernel();
+        Ok(())
+    }
+    pub fn constant(&self) -> f32 {
+        self.constant
+    }
+    /// Changes the constant subtracted from the local means, on a 0 to 255
+    /// scale. Negative values are allowed.
+    pub fn set_constant(&mut self, constant: f32) {
+        self.constant = constant;
+        self.write_settings();
+    }
+    pub fn max_value(&self) -> u32 {
+        self.max_value
+    }
+    /// Changes the value written by the binary modes, 255 by default.
+    pub fn set_max_value(&mut self, max_value: u32) {
+        self.max_value = max_value;
+        self.write_settings();
+    }
+    pub fn mode(&self) -> ThresholdMode {
+        self.mode
+    }
+    pub fn set_mode(&mut self, mode: ThresholdMode) {
+        self.mode = mode;
+        self.write_settings();
+    }
+    pub fn luminance_mask(&self) -> bool {
+        self.luminance_mask
+    }
+    /// Compares BT.601 luminances instead of each channel, see
+    /// `Threshold::set_luminance_mask`.
+    pub fn set_luminance_mask(&mut self, enabled: bool) {
+        self.luminance_mask = enabled;
+        self.write_settings();
+    }
+    pub fn border_mode(&self) -> BorderMode {
+        self.mean.border_mode()
+    }
+    /// Changes how pixels outside the image are read by the next submitted
+    /// run. Defaults to `BorderMode::Replicate`, like OpenCV.
+    pub fn set_border_mode(&mut self, border_mode: BorderMode) {
+        self.mean.set_border_mode(border_mode);
+    }
+}
+
+impl Filter for AdaptiveThreshold {
+    fn context(&self) -> &WgContext {
+        &self.context
+    }
+
+    fn outputs(&self) -> Vec<&WgImageBuffer> {
+        self.images.current().iter().collect()
+    }
+
+    fn output_descriptor(&self, inputs: &[ImageDescriptor]) -> Result<ImageDescriptor> {
+        let (width, height) = common_size(inputs, 1)?;
+        Ok(ImageDescriptor {
+            width,
+            height,
+            format: single_channel_format(
+                &self.context,
+                self.format,
+                self.luminance_mask,
+                inputs[0].format,
+            )?,
+        })
+    }
+
+    fn encode(&mut self, encoder: &mut CommandEncoder, inputs: &[&WgImageBuffer]) -> Result<()> {
+        let descriptor = self.output_descriptor(&descriptors(inputs)?)?;
+        let input_image = inputs[0];
+        let key = (descriptor.format, has_color_channels(input_image.format()));
+        self.pipeline(key.0, key.1)?;
+        self.mean.encode(encoder, inputs)?;
+        let mean_image = self.mean.output_image().expect("mean was encoded");
+        let pipeline = &self.pipelines[&key];
+        let output_image = &self.images.get(&self.context, &[descriptor])?[0];
+        let device = &self.context.device;
+        let settings = &self.settings;
+        let compute_constants = self.constants.entry(key).or_insert_with(|| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Compute constants"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[BindGroupEntry {
+                    binding: 0,
+                    resource: settings.as_entire_binding(),
+                }],
+            })
+        });
+        let image_bind_group =
+            self.bind_groups
+                .get_or_insert_with(&[input_image, mean_image, output_image], || {
+                    device.create_bind_group(&BindGroupDescriptor {
+                        label: Some("Texture bind group"),
+                        layout: &pipeline.get_bind_group_layout(1),
+                        entries: &[
+                            BindGroupEntry {
+                                binding: 0,
+                                resource: BindingResource::TextureView(input_image.view()),
+                            },
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: BindingResource::TextureView(mean_image.view()),
+                            },
+                            BindGroupEntry {
+                                binding: 2,
+                                resource: BindingResource::TextureView(output_image.view()),
+                            },
+                        ],
+                    })
+                });
+        {
+            let (dispatch_width, dispatch_height) = compute_work_group_count(
+                (
+                    input_image.texture_extent.width,
+                    input_image.texture_extent.height,
+                ),
+                (16, 16),
+            );
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, compute_constants, &[]);
+            compute_pass.set_bind_group(1, image_bind_group, &[]);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+        }
+        Ok(())
+    }
+}
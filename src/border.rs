@@ -0,0 +1,40 @@
+/// How neighborhood filters read pixels outside the image.
+///
+/// The examples show the row `abcd` extended by three pixels on each side.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BorderMode {
+    /// `xxx|abcd|xxx` with the given normalized RGBA color.
+    Constant([f32; 4]),
+    /// `aaa|abcd|ddd`
+    Replicate,
+    /// `cba|abcd|dcb`
+    Reflect,
+    /// `dcb|abcd|cba`, the OpenCV default.
+    #[default]
+    Reflect101,
+    /// `bcd|abcd|abc`
+    Wrap,
+}
+
+/// WGSL definitions of the `Border` uniform and `border_index`, prepended to
+/// the source of neighborhood filters.
+pub(crate) const BORDER_SHADER: &str = include_str!("shaders/border.wgsl");
+
+impl BorderMode {
+    /// Contents of the `Border` uniform declared in `BORDER_SHADER`.
+    pub(crate) fn uniform_data(&self) -> [u32; 8] {
+        let (mode, color) = match *self {
+            BorderMode::Constant(color) => (0, color),
+            BorderMode::Replicate => (1, [0.0; 4]),
+            BorderMode::Reflect => (2, [0.0; 4]),
+            BorderMode::Reflect101 => (3, [0.0; 4]),
+            BorderMode::Wrap => (4, [0.0; 4]),
+        };
+        let mut data = [0; 8];
+        data[0] = mode;
+        for (value, channel) in data[4..].iter_mut().zip(color) {
+            *value = channel.to_bits();
+        }
+        data
+    }
+}
@@ -1,24 +1,43 @@
+use std::sync::Arc;
+
+use futures::channel::oneshot;
+use futures::executor::block_on;
+
 use super::context::WgContext;
-use super::utils::padded_bytes_per_row;
-use bytemuck;
-use image;
+use super::error::{Error, Result};
+use super::filter::ImageDescriptor;
+use super::pixel::HostPixel;
+use super::utils::{check_same_size, check_texture_size, padded_bytes_per_row, unpad_rows};
 use wgpu::{
-    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyTexture, Texture,
-    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    Buffer, BufferAsyncError, CommandEncoderDescriptor, Extent3d, ImageCopyTexture, Texture,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDescriptor,
 };
 
 pub struct WgImageBuffer {
-    pub texture: Texture,
+    pub texture: Arc<Texture>,
     pub texture_extent: Extent3d,
+    view: Arc<TextureView>,
 }
 
 impl WgImageBuffer {
-    fn from_host_image_with_additional_flag(
+    fn new(texture: Arc<Texture>, texture_extent: Extent3d) -> Self {
+        let view = Arc::new(texture.create_view(&TextureViewDescriptor::default()));
+        WgImageBuffer {
+            texture,
+            texture_extent,
+            view,
+        }
+    }
+    fn from_host_image_with_additional_flag<P: HostPixel>(
         context: &WgContext,
-        image: image::ImageBuffer<image::Rgba<u8>, Vec<u8>>,
+        image: image::ImageBuffer<P, Vec<P::Subpixel>>,
+        format: TextureFormat,
         additional_flag: TextureUsages,
-    ) -> Self {
+    ) -> Result<Self> {
+        check_format::<P>(format)?;
         let (w, h) = image.dimensions();
+        check_texture_size(context, w, h)?;
         let texture_extent = Extent3d {
             width: w,
             height: h,
@@ -29,44 +48,69 @@ impl WgImageBuffer {
             mip_level_count: 1,
             sample_count: 1,
             dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8Unorm,
+            format,
             usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | additional_flag,
             label: None,
             view_formats: &[],
         });
+        let bytes_per_pixel = texel_size(format)?;
         context.queue.write_texture(
             texture.as_image_copy(),
-            &image,
+            &P::to_format_texels(format, &image),
             wgpu::ImageDataLayout {
                 offset: 0,
-                bytes_per_row: Some(4 * texture_extent.width),
+                bytes_per_row: Some(bytes_per_pixel * texture_extent.width),
                 rows_per_image: Some(texture_extent.height),
             },
             texture_extent,
         );
 
-        WgImageBuffer {
-            texture,
-            texture_extent,
-        }
+        Ok(WgImageBuffer::new(Arc::new(texture), texture_extent))
     }
-    pub fn from_host_image_readonly(
+    pub fn from_host_image_readonly<P: HostPixel>(
         context: &WgContext,
-        image: image::ImageBuffer<image::Rgba<u8>, Vec<u8>>,
-    ) -> Self {
-        Self::from_host_image_with_additional_flag(context, image, TextureUsages::empty())
+        image: image::ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> Result<Self> {
+        Self::from_host_image_with_additional_flag(
+            context,
+            image,
+            P::FORMAT,
+            TextureUsages::empty(),
+        )
     }
-    pub fn from_host_image(
+    pub fn from_host_image<P: HostPixel>(
         context: &WgContext,
-        image: image::ImageBuffer<image::Rgba<u8>, Vec<u8>>,
-    ) -> Self {
+        image: image::ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> Result<Self> {
+        Self::from_host_image_with_format(context, image, P::FORMAT)
+    }
+    /// Uploads `image` into a texture of `format`, which must be
+    /// `P::FORMAT` or one of `P::OTHER_FORMATS`, e.g. `Rgba<f32>` pixels
+    /// into an `Rgba16Float` texture.
+    pub fn from_host_image_with_format<P: HostPixel>(
+        context: &WgContext,
+        image: image::ImageBuffer<P, Vec<P::Subpixel>>,
+        format: TextureFormat,
+    ) -> Result<Self> {
         Self::from_host_image_with_additional_flag(
             context,
             image,
-            TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC,
+            format,
+            storage_usage(context, format) | TextureUsages::COPY_SRC,
         )
     }
-    pub fn from_size(context: &WgContext, width: u32, height: u32) -> WgImageBuffer {
+    pub fn from_size(context: &WgContext, width: u32, height: u32) -> Result<WgImageBuffer> {
+        Self::from_size_with_format(context, width, height, TextureFormat::Rgba8Unorm)
+    }
+    /// Creates an uninitialized image. It is writable by filters only if
+    /// `context.supports_storage(format)`.
+    pub fn from_size_with_format(
+        context: &WgContext,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+    ) -> Result<WgImageBuffer> {
+        check_texture_size(context, width, height)?;
         let texture_extent = Extent3d {
             width,
             height,
@@ -77,38 +121,181 @@ impl WgImageBuffer {
             mip_level_count: 1,
             sample_count: 1,
             dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8Unorm,
+            format,
             usage: TextureUsages::TEXTURE_BINDING
-                | TextureUsages::STORAGE_BINDING
-                | TextureUsages::COPY_SRC,
+                | storage_usage(context, format)
+                | TextureUsages::COPY_SRC
+                | TextureUsages::COPY_DST,
             label: None,
             view_formats: &[],
         });
-        WgImageBuffer {
-            texture,
-            texture_extent,
+        Ok(WgImageBuffer::new(Arc::new(texture), texture_extent))
+    }
+    /// Wraps a texture created elsewhere without copying it.
+    ///
+    /// The texture must be a single sampled, two dimensional texture of an
+    /// uncompressed float or normalized color format. Filters only accept it
+    /// as an input if it has `TEXTURE_BINDING` usage, reading it back needs
+    /// `COPY_SRC` and writing host images needs `COPY_DST`; other uses
+    /// return `Error::UnsupportedTexture`.
+    pub fn from_texture(context: &WgContext, texture: Arc<Texture>) -> Result<Self> {
+        let texture_extent = texture.size();
+        if texture.dimension() != TextureDimension::D2 || texture_extent.depth_or_array_layers != 1
+        {
+            return Err(Error::UnsupportedTexture(
+                "only single layer 2D textures can be wrapped".into(),
+            ));
         }
+        if texture.sample_count() != 1 {
+            return Err(Error::UnsupportedTexture(
+                "multisampled textures cannot be wrapped".into(),
+            ));
+        }
+        if !matches!(
+            texture.format().sample_type(None),
+            Some(TextureSampleType::Float { .. })
+        ) {
+            return Err(Error::UnsupportedTexture(format!(
+                "{:?} textures are not read as floats",
+                texture.format()
+            )));
+        }
+        texel_size(texture.format())?;
+        check_texture_size(context, texture_extent.width, texture_extent.height)?;
+        Ok(WgImageBuffer::new(texture, texture_extent))
+    }
+    /// View of the whole texture, created once and shared by the bind groups
+    /// of every filter reading or writing this image.
+    pub fn view(&self) -> &Arc<TextureView> {
+        &self.view
     }
-    pub fn to_host_image(
+    pub fn format(&self) -> TextureFormat {
+        self.texture.format()
+    }
+    /// Fails if the texture was created without `usage`.
+    pub(crate) fn check_usage(&self, usage: TextureUsages) -> Result<()> {
+        if !self.texture.usage().contains(usage) {
+            return Err(Error::UnsupportedTexture(format!(
+                "texture usage {:?} does not contain {:?}",
+                self.texture.usage(),
+                usage
+            )));
+        }
+        Ok(())
+    }
+    pub fn descriptor(&self) -> ImageDescriptor {
+        ImageDescriptor {
+            width: self.texture_extent.width,
+            height: self.texture_extent.height,
+            format: self.format(),
+        }
+    }
+    /// Copies the image back to the host as pixels of type `P`, which must
+    /// be stored in the texture format, see `HostPixel::supports_format`.
+    ///
+    /// Blocks the calling thread until the copy has finished.
+    pub fn to_host_image<P: HostPixel>(
         &self,
         context: &WgContext,
-    ) -> Option<image::ImageBuffer<image::Rgba<u8>, Vec<u8>>> {
+    ) -> Result<image::ImageBuffer<P, Vec<P::Subpixel>>> {
+        check_format::<P>(self.format())?;
+        let mut readback = self.begin_readback(context)?;
+        context.device.poll(wgpu::Maintain::Wait);
+        block_on(readback.mapped())?;
+        readback.into_image()
+    }
+    /// Non-blocking variant of `to_host_image`.
+    ///
+    /// The returned future only completes once the device has been polled
+    /// after the copy finished, e.g. by calling `WgContext::poll` from an
+    /// event loop or another task. Several readbacks can be in flight at once.
+    pub async fn to_host_image_async<P: HostPixel>(
+        &self,
+        context: &WgContext,
+    ) -> Result<image::ImageBuffer<P, Vec<P::Subpixel>>> {
+        check_format::<P>(self.format())?;
+        let mut readback = self.begin_readback(context)?;
+        readback.mapped().await?;
+        readback.into_image()
+    }
+    /// Copies the raw texels of the image, without row padding, into `dst`,
+    /// which must hold exactly `width * height * bytes_per_pixel` bytes.
+    pub fn read_into(&self, context: &WgContext, dst: &mut [u8]) -> Result<()> {
+        self.check_host_len(dst.len())?;
+        let mut readback = self.begin_readback(context)?;
+        context.device.poll(wgpu::Maintain::Wait);
+        block_on(readback.mapped())?;
+        readback.read_into(dst);
+        Ok(())
+    }
+    /// Non-blocking variant of `read_into`.
+    pub async fn read_into_async(&self, context: &WgContext, dst: &mut [u8]) -> Result<()> {
+        self.check_host_len(dst.len())?;
+        let mut readback = self.begin_readback(context)?;
+        readback.mapped().await?;
+        readback.read_into(dst);
+        Ok(())
+    }
+    /// Overwrites the image with `image`, reusing the existing texture.
+    pub fn write_host_image<P: HostPixel>(
+        &self,
+        context: &WgContext,
+        image: &image::ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> Result<()> {
+        check_format::<P>(self.format())?;
+        let extent = Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        check_same_size(self.texture_extent, extent)?;
+        self.check_usage(TextureUsages::COPY_DST)?;
+        let bytes_per_pixel = self.bytes_per_pixel()?;
+        context.queue.write_texture(
+            self.texture.as_image_copy(),
+            &P::to_format_texels(self.format(), image),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_pixel * self.texture_extent.width),
+                rows_per_image: Some(self.texture_extent.height),
+            },
+            self.texture_extent,
+        );
+        Ok(())
+    }
+    fn bytes_per_pixel(&self) -> Result<u32> {
+        texel_size(self.format())
+    }
+    fn check_host_len(&self, len: usize) -> Result<()> {
+        let expected = self.texture_extent.width as usize
+            * self.texture_extent.height as usize
+            * self.bytes_per_pixel()? as usize;
+        if len != expected {
+            return Err(Error::InvalidLength {
+                expected,
+                actual: len,
+            });
+        }
+        Ok(())
+    }
+    /// Submits a copy of the texture into a pooled staging buffer and
+    /// requests mapping it for reading.
+    fn begin_readback(&self, context: &WgContext) -> Result<Readback> {
+        self.check_usage(TextureUsages::COPY_SRC)?;
         let mut encoder = context
             .device
             .create_command_encoder(&CommandEncoderDescriptor { label: None });
 
-        let padded_bytes_per_row = padded_bytes_per_row(self.texture_extent.width);
-        let unpadded_bytes_per_row = self.texture_extent.width as usize * 4;
+        let bytes_per_pixel = self.bytes_per_pixel()?;
+        let padded_bytes_per_row = padded_bytes_per_row(self.texture_extent.width, bytes_per_pixel);
+        let unpadded_bytes_per_row = self.texture_extent.width as usize * bytes_per_pixel as usize;
 
         let output_buffer_size = padded_bytes_per_row as u64
             * self.texture_extent.height as u64
             * std::mem::size_of::<u8>() as u64;
-        let output_buffer = context.device.create_buffer(&BufferDescriptor {
-            label: None,
-            size: output_buffer_size,
-            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
-            mapped_at_creation: false,
-        });
+        let output_buffer = context
+            .staging_pool()
+            .acquire(&context.device, output_buffer_size);
 
         encoder.copy_texture_to_buffer(
             ImageCopyTexture {
@@ -129,26 +316,107 @@ impl WgImageBuffer {
         );
         context.queue.submit(Some(encoder.finish()));
 
-        let buffer_slice = output_buffer.slice(..);
-        buffer_slice.map_async(wgpu::MapMode::Read, |_| {});
+        let (sender, receiver) = oneshot::channel();
+        output_buffer
+            .slice(..output_buffer_size)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
 
-        context.device.poll(wgpu::Maintain::Wait);
+        Ok(Readback {
+            context: context.clone(),
+            buffer: output_buffer,
+            size: output_buffer_size,
+            receiver,
+            texture_extent: self.texture_extent,
+            format: self.format(),
+            padded_bytes_per_row,
+            unpadded_bytes_per_row,
+        })
+    }
+}
+
+/// Size in bytes of one texel of `format`, failing for formats without
+/// per-texel storage such as compressed and depth-stencil formats.
+fn texel_size(format: TextureFormat) -> Result<u32> {
+    match format.block_size(None) {
+        Some(size) if format.block_dimensions() == (1, 1) => Ok(size),
+        _ => Err(Error::UnsupportedTexture(format!(
+            "{:?} has no per-texel size",
+            format
+        ))),
+    }
+}
+
+fn check_format<P: HostPixel>(format: TextureFormat) -> Result<()> {
+    if !P::supports_format(format) {
+        return Err(Error::FormatMismatch {
+            expected: P::FORMAT,
+            actual: format,
+        });
+    }
+    Ok(())
+}
 
-        let padded_data = buffer_slice.get_mapped_range();
+/// Staging buffer of a readback whose mapping may still be pending.
+struct Readback {
+    context: WgContext,
+    buffer: Buffer,
+    size: u64,
+    receiver: oneshot::Receiver<std::result::Result<(), BufferAsyncError>>,
+    texture_extent: Extent3d,
+    format: TextureFormat,
+    padded_bytes_per_row: usize,
+    unpadded_bytes_per_row: usize,
+}
 
-        let mut flat_pixels =
-            vec![0; unpadded_bytes_per_row * (self.texture_extent.height as usize)];
-        for (padded, flat_pixels) in padded_data
-            .chunks_exact(padded_bytes_per_row)
-            .zip(flat_pixels.chunks_exact_mut(padded_bytes_per_row))
+impl Readback {
+    /// Resolves once the map callback has run, propagating its error.
+    async fn mapped(&mut self) -> Result<()> {
+        (&mut self.receiver)
+            .await
+            .unwrap_or(Err(BufferAsyncError))?;
+        Ok(())
+    }
+    /// Copies the mapped rows into `dst` and returns the buffer to the pool.
+    fn read_into(self, dst: &mut [u8]) {
         {
-            flat_pixels.copy_from_slice(bytemuck::cast_slice(&padded[..unpadded_bytes_per_row]));
+            let padded_data = self.buffer.slice(..self.size).get_mapped_range();
+            unpad_rows(
+                &padded_data,
+                dst,
+                self.padded_bytes_per_row,
+                self.unpadded_bytes_per_row,
+            );
         }
+        self.buffer.unmap();
+        self.context.staging_pool().release(self.buffer);
+    }
+    fn into_image<P: HostPixel>(self) -> Result<image::ImageBuffer<P, Vec<P::Subpixel>>> {
+        let (width, height) = (self.texture_extent.width, self.texture_extent.height);
+        let format = self.format;
+        let mut flat_pixels = vec![0; self.unpadded_bytes_per_row * height as usize];
+        self.read_into(&mut flat_pixels);
 
-        image::ImageBuffer::<image::Rgba<u8>, Vec<u8>>::from_raw(
-            self.texture_extent.width,
-            self.texture_extent.height,
-            flat_pixels,
+        let subpixels = P::from_format_texels(format, flat_pixels);
+        let subpixel_size = std::mem::size_of::<P::Subpixel>();
+        let actual = subpixels.len() * subpixel_size;
+        image::ImageBuffer::<P, Vec<P::Subpixel>>::from_raw(width, height, subpixels).ok_or(
+            Error::InvalidLength {
+                expected: width as usize
+                    * height as usize
+                    * P::CHANNEL_COUNT as usize
+                    * subpixel_size,
+                actual,
+            },
         )
     }
 }
+
+fn storage_usage(context: &WgContext, format: TextureFormat) -> TextureUsages {
+    if context.supports_storage(format) {
+        TextureUsages::STORAGE_BINDING
+    } else {
+        TextureUsages::empty()
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+use wgpu::{
+    util::BufferInitDescriptor, BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource,
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, ComputePipeline, TextureFormat,
+};
+
+use super::buffer::WgImageBuffer;
+use super::context::WgContext;
+use super::error::Result;
+use super::filter::{
+    common_size, descriptors, has_color_channels, output_format, BindGroupCache, Filter,
+    ImageCache, ImageDescriptor,
+};
+use super::gaussian_blur::GaussianBlur;
+use super::gradient::{Gradient, GradientOperator};
+use super::utils::{
+    compute_work_group_count, shader_with_output_format, storage_buffer_entry,
+    storage_texture_entry, texture_entry, uniform_entry,
+};
+
+const CANNY_SHADER: &str = include_str!("shaders/canny.wgsl");
+
+/// How `Canny` measures gradient magnitudes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GradientNorm {
+    /// `|dx| + |dy|`, OpenCV's default.
+    #[default]
+    L1,
+    /// `sqrt(dx² + dy²)`, more accurate.
+    L2,
+}
+
+struct CannyPipelines {
+    suppress: Arc<ComputePipeline>,
+    prepare: Arc<ComputePipeline>,
+    hysteresis: Arc<ComputePipeline>,
+    finalize: Arc<ComputePipeline>,
+}
+
+/// Canny edge detector.
+///
+/// The input is smoothed by a Gaussian blur, differentiated with a 3x3 Sobel
+/// operator on its luminance, or on the red channel of inputs without color
+/// channels, thinned by non-maximum suppression and classified by a double
+/// threshold. Weak edges connected to strong ones are then promoted on the GPU
+/// until no edge changes. Edges are written as white pixels on a black
+/// background.
+///
+/// Because `encode` only records commands, it cannot wait for hysteresis to
+/// converge: it records up to `max_iterations` passes, and those recorded
+/// after convergence dispatch no workgroups. Hysteresis is thus iterated until
+/// convergence or until this cap, whichever comes first.
+pub struct Canny {
+    context: WgContext,
+    format: Option<TextureFormat>,
+    blur: GaussianBlur,
+    gradient: Gradient,
+    pipelines: HashMap<TextureFormat, CannyPipelines>,
+    images: ImageCache,
+    /// Settings and indirect dispatch bind groups of each pipeline set.
+    constants: HashMap<TextureFormat, (BindGroup, BindGroup)>,
+    bind_groups: BindGroupCache,
+    settings: Buffer,
+    /// Edge class of every pixel, grown to the largest input.
+    state: Buffer,
+    control: Buffer,
+    dispatch: Buffer,
+    low_threshold: f32,
+    high_threshold: f32,
+    sigma: f32,
+    norm: GradientNorm,
+    max_iterations: Option<u32>,
+}
+
+impl Canny {
+    /// Writes outputs in the input format when possible, see `with_format`.
+    ///
+    /// Thresholds apply to gradient magnitudes of 8-bit images, like OpenCV's,
+    /// and are swapped if `low_threshold` is the greater one.
+    pub fn new(
+        context: &WgContext,
+        sigma: f32,
+        low_threshold: f32,
+        high_threshold: f32,
+    ) -> Result<Self> {
+        let settings = context.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Image info"),
+            contents: &[0; 16],
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let control = context.device.create_buffer(&BufferDescriptor {
+            label: Some("Hysteresis control"),
+            size: 16,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let dispatch = context.device.create_buffer(&BufferDescriptor {
+            label: Some("Hysteresis dispatch"),
+            size: 12,
+            usage: BufferUsages::STORAGE | BufferUsages::INDIRECT,
+            mapped_at_creation: false,
+        });
+        let mut canny = Canny {
+            context: context.clone(),
+            format: None,
+            blur: GaussianBlur::with_format(context, sigma, TextureFormat::Rgba32Float)?,
+            gradient: Gradient::with_format(
+                context,
+                GradientOperator::Sobel3,
+                TextureFormat::Rgba32Float,
+            )?,
+            pipelines: HashMap::new(),
+            images: ImageCache::default(),
+            constants: HashMap::new(),
+            bind_groups: BindGroupCache::default(),
+            settings,
+            state: create_state_buffer(context, 1),
+            control,
+            dispatch,
+            low_threshold,
+            high_threshold,
+            sigma,
+            norm: GradientNorm::default(),
+            max_iterations: None,
+        };
+        canny.set_thresholds(low_threshold, high_threshold);
+        Ok(canny)
+    }
+    /// Always writes outputs of `format`.
+    pub fn with_format(
+        context: &WgContext,
+        sigma: f32,
+        low_threshold: f32,
+        high_threshold: f32,
+        format: TextureFormat,
+    ) -> Result<Self> {
+        let mut filter = Self::new(context, sigma, low_threshold, high_threshold)?;
+        filter.format = Some(format);
+        filter.pipelines(format)?;
+        Ok(filter)
+    }
+    fn pipelines(&mut self, format: TextureFormat) -> Result<&CannyPipelines> {
+        if !self.pipelines.contains_key(&format) {
+            let shader = shader_with_output_format(&self.context, CANNY_SHADER, format)?;
+            let constants = [
+                uniform_entry(0),
+                storage_buffer_entry(1, false),
+                storage_buffer_entry(2, false),
+            ];
+            let images = [texture_entry(0), storage_texture_entry(1, format)];
+            let create = |entry_point: &str| {
+                self.context.create_compute_pipeline(
+                    "canny",
+                    &shader,
+                    entry_point,
+                    &[&constants, &images],
+                )
+            };
+            let pipelines = CannyPipelines {
+                suppress: create("suppress")?,
+                prepare: self.context.create_compute_pipeline(
+                    "canny",
+                    &shader,
+                    "prepare",
+                    &[&constants, &images, &[storage_buffer_entry(0, false)]],
+                )?,
+                hysteresis: create("hysteresis")?,
+                finalize: create("finalize")?,
+            };
+            self.pipelines.insert(format, pipelines);
+        }
+        Ok(&self.pipelines[&format])
+    }
+    fn write_settings(&self) {
+        let settings = [
+            f32::to_bits(self.low_threshold),
+            f32::to_bits(self.high_threshold),
+            (self.norm == GradientNorm::L2) as u32,
+            0,
+        ];
+        self.context
+            .queue
+            .write_buffer(&self.settings, 0, bytemuck::cast_slice(&settings));
+    }
+    /// Low and high thresholds.
+    pub fn thresholds(&self) -> (f32, f32) {
+        (self.low_threshold, self.high_threshold)
+    }
+    /// Changes the thresholds used by the next submitted run.
+    pub fn set_thresholds(&mut self, low_threshold: f32, high_threshold: f32) {
+        self.low_threshold = low_threshold.min(high_threshold);
+        self.high_threshold = low_threshold.max(high_threshold);
+        self.write_settings();
+    }
+    pub fn sigma(&self) -> f32 {
+        self.sigma
+    }
+    /// Changes the sigma of the Gaussian blur applied before differentiating.
+    pub fn set_sigma(&mut self, sigma: f32) {
+        self.sigma = sigma;
+        self.blur.set_sigma(sigma);
+    }
+    pub fn norm(&self) -> GradientNorm {
+        self.norm
+    }
+    pub fn set_norm(&mut self, norm: GradientNorm) {
+        self.norm = norm;
+        self.write_settings();
+    }
+    pub fn max_iterations(&self) -> Option<u32> {
+        self.max_iterations
+    }
+    /// Limits the hysteresis passes recorded per run, a deliberate cap on the
+    /// iteration until convergence. Each pass propagates edges through 16x16
+    /// tiles and the ones recorded after convergence do no work, but each
+    /// still costs two dispatches on the CPU.
+    ///
+    /// Defaults to twice the number of tiles along the width and height, e.g.
+    /// 750 passes at 3840x2160. That lets an edge cross the image back
+    /// and forth, enough for all but pathological spiralling edges, which are
+    /// then left partially promoted. Lower it to bound the cost on large
+    /// images, or raise it if such edges must be traced in full.
+    pub fn set_max_iterations(&mut self, max_iterations: Option<u32>) {
+        self.max_iterations = max_iterations;
+    }
+}
+
+fn create_state_buffer(context: &WgContext, pixels: u64) -> Buffer {
+    context.device.create_buffer(&BufferDescriptor {
+        label: Some("Edge state"),
+        size: pixels * 4,
+        usage: BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    })
+}
+
+impl Filter for Canny {
+    fn context(&self) -> &WgContext {
+        &self.context
+    }
+
+    fn outputs(&self) -> Vec<&WgImageBuffer> {
+        self.images.current().iter().collect()
+    }
+
+    fn output_descriptor(&self, inputs: &[ImageDescriptor]) -> Result<ImageDescriptor> {
+        let (width, height) = common_size(inputs, 1)?;
+        Ok(ImageDescriptor {
+            width,
+            height,
+            format: output_format(&self.context, self.format, inputs[0].format),
+        })
+    }
+
+    fn encode(&mut self, encoder: &mut CommandEncoder, inputs: &[&WgImageBuffer]) -> Result<()> {
+        let descriptor = self.output_descriptor(&descriptors(inputs)?)?;
+        self.pipelines(descriptor.format)?;
+        let pixels = descriptor.width as u64 * descriptor.height as u64;
+        if self.state.size() < pixels * 4 {
+            self.state = create_state_buffer(&self.context, pixels);
+            self.constants.clear();
+        }
+        self.blur.encode(encoder, inputs)?;
+        let blurred_image = self.blur.output_image().expect("blur was encoded");
+        // The blurred copy is always RGBA, keep differentiating single-channel
+        // inputs on their only channel.
+        self.gradient
+            .set_color_channels(Some(has_color_channels(inputs[0].format())));
+        self.gradient.encode(encoder, &[blurred_image])?;
+        let gradient_image = self.gradient.output_image().expect("gradient was encoded");
+        let pipelines = &self.pipelines[&descriptor.format];
+        let output_image = &self.images.get(&self.context, &[descriptor])?[0];
+        let device = &self.context.device;
+        let (settings, state, control, dispatch) =
+            (&self.settings, &self.state, &self.control, &self.dispatch);
+        let (compute_constants, dispatch_bind_group) =
+            self.constants.entry(descriptor.format).or_insert_with(|| {
+                let constants = device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("Compute constants"),
+                    layout: &pipelines.suppress.get_bind_group_layout(0),
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: settings.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: state.as_entire_binding(),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: control.as_entire_binding(),
+                        },
+                    ],
+                });
+                let dispatch_bind_group = device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("Dispatch bind group"),
+                    layout: &pipelines.prepare.get_bind_group_layout(2),
+                    entries: &[BindGroupEntry {
+                        binding: 0,
+                        resource: dispatch.as_entire_binding(),
+                    }],
+                });
+                (constants, dispatch_bind_group)
+            });
+        let image_bind_group =
+            self.bind_groups
+                .get_or_insert_with(&[gradient_image, output_image], || {
+                    device.create_bind_group(&BindGroupDescriptor {
+                        label: Some("Texture bind group"),
+                        layout: &pipelines.suppress.get_bind_group_layout(1),
+                        entries: &[
+                            BindGroupEntry {
+                                binding: 0,
+                                resource: BindingResource::TextureView(gradient_image.view()),
+                            },
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: BindingResource::TextureView(output_image.view()),
+                            },
+                        ],
+                    })
+                });
+        {
+            let (dispatch_width, dispatch_height) =
+                compute_work_group_count((descriptor.width, descriptor.height), (16, 16));
+            let max_iterations = self
+                .max_iterations
+                .unwrap_or(2 * (dispatch_width + dispatch_height));
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            compute_pass.set_bind_group(0, compute_constants, &[]);
+            compute_pass.set_bind_group(1, image_bind_group, &[]);
+            compute_pass.set_bind_group(2, dispatch_bind_group, &[]);
+            compute_pass.set_pipeline(&pipelines.suppress);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+            for _ in 0..max_iterations {
+                compute_pass.set_pipeline(&pipelines.prepare);
+                compute_pass.dispatch_workgroups(1, 1, 1);
+                compute_pass.set_pipeline(&pipelines.hysteresis);
+                compute_pass.dispatch_workgroups_indirect(dispatch, 0);
+            }
+            compute_pass.set_pipeline(&pipelines.finalize);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+        }
+        Ok(())
+    }
+}
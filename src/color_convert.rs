@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+use wgpu::{
+    util::BufferInitDescriptor, BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource,
+    Buffer, BufferUsages, CommandEncoder, ComputePipeline, TextureFormat,
+};
+
+use super::buffer::WgImageBuffer;
+use super::context::WgContext;
+use super::error::Result;
+use super::filter::{
+    common_size, descriptors, output_format, BindGroupCache, Filter, ImageCache, ImageDescriptor,
+};
+use super::utils::{
+    compute_work_group_count, shader_with_output_format, storage_texture_entry, texture_entry,
+    uniform_entry,
+};
+
+const COLOR_CONVERT_SHADER: &str = include_str!("shaders/color_convert.wgsl");
+
+/// Luma coefficients of a YCbCr encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum YCbCrMatrix {
+    /// ITU-R BT.601, as used by JPEG and SD video.
+    #[default]
+    Bt601,
+    /// ITU-R BT.709, for HD video.
+    Bt709,
+}
+
+impl YCbCrMatrix {
+    /// Red and blue luma weights, Kr and Kb.
+    pub fn coefficients(self) -> (f32, f32) {
+        match self {
+            YCbCrMatrix::Bt601 => (0.299, 0.114),
+            YCbCrMatrix::Bt709 => (0.2126, 0.0722),
+        }
+    }
+}
+
+/// Range of the digital values of a YCbCr encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorRange {
+    /// Luma and chroma use the whole 0 to 255 range, like JPEG.
+    #[default]
+    Full,
+    /// Luma is kept in 16 to 235 and chroma in 16 to 240, like video.
+    Limited,
+}
+
+/// Color spaces `ColorConvert` converts between, in the first three
+/// channels of an image. Alpha is copied as is.
+///
+/// RGB values are sRGB encoded, and XYZ, Lab and Luv use the D65 white point
+/// like OpenCV's float conversions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// sRGB encoded red, green and blue in 0 to 1.
+    #[default]
+    Rgb,
+    /// sRGB primaries without the transfer function.
+    LinearRgb,
+    /// Hue in degrees, saturation and value in 0 to 1.
+    Hsv,
+    /// Hue in degrees, saturation and lightness in 0 to 1.
+    Hsl,
+    /// Y, Cb and Cr scaled like 8-bit values divided by 255, with chroma
+    /// centered on 128 / 255.
+    YCbCr(YCbCrMatrix, ColorRange),
+    /// CIE 1931 XYZ, with Y in 0 to 1.
+    Xyz,
+    /// CIE L*a*b*, with L in 0 to 100 and a and b roughly in -128 to 127.
+    Lab,
+    /// CIE L*u*v*, with L in 0 to 100, u in -134 to 220 and v in -140 to 122.
+    Luv,
+}
+
+impl ColorSpace {
+    fn shader_index(self) -> u32 {
+        match self {
+            ColorSpace::Rgb => 0,
+            ColorSpace::LinearRgb => 1,
+            ColorSpace::Hsv => 2,
+            ColorSpace::Hsl => 3,
+            ColorSpace::YCbCr(..) => 4,
+            ColorSpace::Xyz => 5,
+            ColorSpace::Lab => 6,
+            ColorSpace::Luv => 7,
+        }
+    }
+    /// Kr, Kb and whether the range is limited, zeros for other spaces.
+    fn ycbcr_settings(self) -> [f32; 4] {
+        match self {
+            ColorSpace::YCbCr(matrix, range) => {
+                let (kr, kb) = matrix.coefficients();
+                [kr, kb, (range == ColorRange::Limited) as u32 as f32, 0.0]
+            }
+            _ => [0.0; 4],
+        }
+    }
+    /// Whether values fall outside 0 to 1 or lose too much precision in 8
+    /// bits.
+    pub fn needs_float(self) -> bool {
+        !matches!(self, ColorSpace::Rgb | ColorSpace::YCbCr(..))
+    }
+}
+
+/// Converts images between color spaces, through sRGB when neither side is
+/// RGB.
+///
+/// Converting to a space that needs float values writes `Rgba32Float`
+/// outputs unless another format is requested, and inverse conversions read
+/// them back without loss.
+pub struct ColorConvert {
+    context: WgContext,
+    format: Option<TextureFormat>,
+    pipelines: HashMap<TextureFormat, Arc<ComputePipeline>>,
+    images: ImageCache,
+    /// Settings bind group of each pipeline.
+    constants: HashMap<TextureFormat, BindGroup>,
+    bind_groups: BindGroupCache,
+    settings: Buffer,
+    from: ColorSpace,
+    to: ColorSpace,
+}
+
+impl ColorConvert {
+    /// Writes `Rgba32Float` outputs for spaces that need float values, and
+    /// outputs in the input format when possible otherwise, see
+    /// `with_format`.
+    pub fn new(context: &WgContext, from: ColorSpace, to: ColorSpace) -> Result<Self> {
+        let settings = context.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Image info"),
+            contents: &[0; 48],
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let filter = ColorConvert {
+            context: context.clone(),
+            format: None,
+            pipelines: HashMap::new(),
+            images: ImageCache::default(),
+            constants: HashMap::new(),
+            bind_groups: BindGroupCache::default(),
+            settings,
+            from,
+            to,
+        };
+        filter.write_settings();
+        Ok(filter)
+    }
+    /// Always writes outputs of `format`.
+    pub fn with_format(
+        context: &WgContext,
+        from: ColorSpace,
+        to: ColorSpace,
+        format: TextureFormat,
+    ) -> Result<Self> {
+        let mut filter = Self::new(context, from, to)?;
+        filter.format = Some(format);
+        filter.pipeline(format)?;
+        Ok(filter)
+    }
+    fn pipeline(&mut self, format: TextureFormat) -> Result<&ComputePipeline> {
+        if !self.pipelines.contains_key(&format) {
+            let shader = shader_with_output_format(&self.context, COLOR_CONVERT_SHADER, format)?;
+            let pipeline = self.context.create_compute_pipeline(
+                "color convert",
+                &shader,
+                "main",
+                &[
+                    &[uniform_entry(0)],
+                    &[texture_entry(0), storage_texture_entry(1, format)],
+                ],
+            )?;
+            self.pipelines.insert(format, pipeline);
+        }
+        Ok(&self.pipelines[&format])
+    }
+    fn write_settings(&self) {
+        let [from_kr, from_kb, from_limited, _] = self.from.ycbcr_settings();
+        let [to_kr, to_kb, to_limited, _] = self.to.ycbcr_settings();
+        let settings = [
+            self.from.shader_index(),
+            self.to.shader_index(),
+            0,
+            0,
+            f32::to_bits(from_kr),
+            f32::to_bits(from_kb),
+            f32::to_bits(from_limited),
+            0,
+            f32::to_bits(to_kr),
+            f32::to_bits(to_kb),
+            f32::to_bits(to_limited),
+            0,
+        ];
+        self.context
+            .queue
+            .write_buffer(&self.settings, 0, bytemuck::cast_slice(&settings));
+    }
+    /// Space of the inputs.
+    pub fn from_space(&self) -> ColorSpace {
+        self.from
+    }
+    /// Space of the outputs.
+    pub fn to_space(&self) -> ColorSpace {
+        self.to
+    }
+    /// Changes the spaces converted between by the next submitted run.
+    pub fn set_spaces(&mut self, from: ColorSpace, to: ColorSpace) {
+        self.from = from;
+        self.to = to;
+        self.write_settings();
+    }
+}
+
+impl Filter for ColorConvert {
+    fn context(&self) -> &WgContext {
+        &self.context
+    }
+
+    fn outputs(&self) -> Vec<&WgImageBuffer> {
+        self.images.current().iter().collect()
+    }
+
+    fn output_descriptor(&self, inputs: &[ImageDescriptor]) -> Result<ImageDescriptor> {
+        let (width, height) = common_size(inputs, 1)?;
+        let requested = match self.format {
+            None if self.to.needs_float() => Some(TextureFormat::Rgba32Float),
+            format => format,
+        };
+        Ok(ImageDescriptor {
+            width,
+            height,
+            format: output_format(&self.context, requested, inputs[0].format),
+        })
+    }
+
+    fn encode(&mut self, encoder: &mut CommandEncoder, inputs: &[&WgImageBuffer]) -> Result<()> {
+        let descriptor = self.output_descriptor(&descriptors(inputs)?)?;
+        let input_image = inputs[0];
+        self.pipeline(descriptor.format)?;
+        let pipeline = &self.pipelines[&descriptor.format];
+        let output_image = &self.images.get(&self.context, &[descriptor])?[0];
+        let device = &self.context.device;
+        let settings = &self.settings;
+        let compute_constants = self.constants.entry(descriptor.format).or_insert_with(|| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Compute constants"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[BindGroupEntry {
+                    binding: 0,
+                    resource: settings.as_entire_binding(),
+                }],
+            })
+        });
+        let image_bind_group =
+            self.bind_groups
+                .get_or_insert_with(&[input_image, output_image], || {
+                    device.create_bind_group(&BindGroupDescriptor {
+                        label: Some("Texture bind group"),
+                        layout: &pipeline.get_bind_group_layout(1),
+                        entries: &[
+                            BindGroupEntry {
+                                binding: 0,
+                                resource: BindingResource::TextureView(input_image.view()),
+                            },
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: BindingResource::TextureView(output_image.view()),
+                            },
+                        ],
+                    })
+                });
+        {
+            let (dispatch_width, dispatch_height) = compute_work_group_count(
+                (
+                    input_image.texture_extent.width,
+                    input_image.texture_extent.height,
+                ),
+                (16, 16),
+            );
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, compute_constants, &[]);
+            compute_pass.set_bind_group(1, image_bind_group, &[]);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+        }
+        Ok(())
+    }
+}
@@ -1,26 +1,276 @@
-use wgpu::{Device, Instance, InstanceDescriptor, PowerPreference, Queue};
+use std::sync::{Arc, Mutex};
 
+use futures::executor::block_on;
+use wgpu::{
+    Adapter, AdapterInfo, Backends, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    ComputePipeline, ComputePipelineDescriptor, Device, DeviceDescriptor, ErrorFilter, Features,
+    Instance, InstanceDescriptor, Limits, PipelineLayoutDescriptor, PowerPreference, Queue,
+    ShaderModuleDescriptor, ShaderSource, TextureFormat, TextureUsages,
+};
+
+use super::error::{Error, Result};
+use super::shader_cache::ShaderCache;
+use super::staging::StagingPool;
+
+/// Device and queue shared by images and filters.
+///
+/// Cloning is cheap: clones share the same device and queue. On native targets
+/// the context, image buffers and filters are `Send + Sync`, so they can be
+/// stored in long-lived structs and moved into other threads or async tasks.
+#[derive(Clone)]
 pub struct WgContext {
-    pub device: Device,
-    pub queue: Queue,
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    adapter: Option<Arc<Adapter>>,
+    staging_pool: Arc<StagingPool>,
+    shader_cache: Arc<ShaderCache>,
+    /// Serializes pipeline creation, as error scopes are shared by the whole
+    /// device.
+    error_scope: Arc<Mutex<()>>,
 }
 
 impl WgContext {
-    pub async fn new() -> Self {
-        let instance = Instance::new(InstanceDescriptor::default());
+    /// Creates a context on the default high performance adapter.
+    pub async fn new() -> Result<Self> {
+        WgContextBuilder::new().build().await
+    }
+
+    /// Wraps a device and queue owned by the caller, e.g. a renderer, so that
+    /// textures can be shared with wgimage filters.
+    ///
+    /// Passing the adapter the device was requested from lets
+    /// `supports_storage` report its format features.
+    pub fn from_device_queue(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        adapter: Option<Arc<Adapter>>,
+    ) -> Self {
+        WgContext {
+            device,
+            queue,
+            adapter,
+            staging_pool: Default::default(),
+            shader_cache: Default::default(),
+            error_scope: Default::default(),
+        }
+    }
+
+    pub fn builder() -> WgContextBuilder {
+        WgContextBuilder::new()
+    }
+
+    /// Readback buffers shared by all clones of this context.
+    pub fn staging_pool(&self) -> &StagingPool {
+        &self.staging_pool
+    }
+
+    /// Shader modules and pipelines shared by all clones of this context.
+    pub fn shader_cache(&self) -> &ShaderCache {
+        &self.shader_cache
+    }
+
+    /// Processes finished GPU work without blocking, resolving pending
+    /// `to_host_image_async` futures. Returns `true` if the queue is empty.
+    pub fn poll(&self) -> bool {
+        self.device.poll(wgpu::Maintain::Poll)
+    }
+
+    /// Blocks until all submitted work has finished.
+    pub fn wait(&self) {
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+
+    /// Whether compute shaders on this device can write textures of `format`.
+    ///
+    /// When the context knows its adapter, i.e. it was created by
+    /// `WgContextBuilder` or given the adapter in `from_device_queue`, formats
+    /// the adapter cannot write are excluded even if WebGPU guarantees them,
+    /// as happens with `Rg32Float` on OpenGL. Formats outside the WebGPU
+    /// guarantees, such as `R8Unorm`, additionally require the device to be
+    /// requested with `Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`.
+    pub fn supports_storage(&self, format: TextureFormat) -> bool {
+        let features = self.device.features();
+        let guaranteed = format.guaranteed_format_features(features).allowed_usages;
+        let allowed_usages = match &self.adapter {
+            Some(adapter) => {
+                let adapter_usages = adapter.get_texture_format_features(format).allowed_usages;
+                if features.contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+                    adapter_usages
+                } else {
+                    guaranteed & adapter_usages
+                }
+            }
+            None => guaranteed,
+        };
+        allowed_usages.contains(TextureUsages::STORAGE_BINDING)
+    }
+
+    /// Compiles `source` and builds a compute pipeline for `entry_point`,
+    /// reporting validation failures instead of panicking.
+    ///
+    /// Each element of `bind_group_layouts` describes one bind group. Pass an
+    /// empty slice to derive the layout from the shader; note that derived
+    /// layouts require filterable textures, which excludes `R32Float` and
+    /// `Rgba32Float` inputs.
+    ///
+    /// Modules and pipelines are looked up in `shader_cache` first, so the
+    /// same source is only compiled once per context.
+    ///
+    /// Validation errors are caught with a device-wide error scope. Clones of
+    /// a context take turns, so filters can be created from several threads,
+    /// but a caller sharing the device through `from_device_queue` must not
+    /// push its own error scopes while a filter is being created.
+    pub fn create_compute_pipeline(
+        &self,
+        label: &str,
+        source: &str,
+        entry_point: &str,
+        bind_group_layouts: &[&[BindGroupLayoutEntry]],
+    ) -> Result<Arc<ComputePipeline>> {
+        if let Some(pipeline) = self
+            .shader_cache
+            .pipeline(source, entry_point, bind_group_layouts)
+        {
+            return Ok(pipeline);
+        }
+        let _error_scope = self.error_scope.lock().unwrap();
+        self.device.push_error_scope(ErrorFilter::Validation);
+        let cached_shader = self.shader_cache.module(source);
+        let shader = cached_shader.clone().unwrap_or_else(|| {
+            Arc::new(self.device.create_shader_module(ShaderModuleDescriptor {
+                label: Some(&format!("{} shader", label)),
+                source: ShaderSource::Wgsl(source.into()),
+            }))
+        });
+        let bind_group_layout_objects: Vec<_> = bind_group_layouts
+            .iter()
+            .map(|entries| {
+                self.device
+                    .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                        label: None,
+                        entries,
+                    })
+            })
+            .collect();
+        let layout = (!bind_group_layout_objects.is_empty()).then(|| {
+            self.device
+                .create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: Some(&format!("{} pipeline layout", label)),
+                    bind_group_layouts: &bind_group_layout_objects.iter().collect::<Vec<_>>(),
+                    push_constant_ranges: &[],
+                })
+        });
+        let pipeline = self
+            .device
+            .create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(&format!("{} pipeline", label)),
+                layout: layout.as_ref(),
+                module: &shader,
+                entry_point,
+            });
+        if let Some(error) = block_on(self.device.pop_error_scope()) {
+            return Err(Error::ShaderValidation(error.to_string()));
+        }
+        if cached_shader.is_none() {
+            self.shader_cache.insert_module(source, shader);
+        }
+        let pipeline = Arc::new(pipeline);
+        self.shader_cache.insert_pipeline(
+            source,
+            entry_point,
+            bind_group_layouts,
+            pipeline.clone(),
+        );
+        Ok(pipeline)
+    }
+}
+
+/// Options used to pick an adapter and request a device.
+#[derive(Clone, Debug)]
+pub struct WgContextBuilder {
+    backends: Backends,
+    power_preference: PowerPreference,
+    force_fallback_adapter: bool,
+    features: Features,
+    limits: Limits,
+}
+
+impl Default for WgContextBuilder {
+    fn default() -> Self {
+        WgContextBuilder {
+            backends: Backends::all(),
+            power_preference: PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            features: Features::empty(),
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl WgContextBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn backends(mut self, backends: Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+    pub fn power_preference(mut self, power_preference: PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+    /// Only accept a software adapter such as llvmpipe or WARP.
+    pub fn force_fallback_adapter(mut self, force_fallback_adapter: bool) -> Self {
+        self.force_fallback_adapter = force_fallback_adapter;
+        self
+    }
+    pub fn features(mut self, features: Features) -> Self {
+        self.features = features;
+        self
+    }
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+    /// Lists the adapters available on the selected backends.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn enumerate_adapters(&self) -> Vec<AdapterInfo> {
+        self.instance()
+            .enumerate_adapters(self.backends)
+            .map(|adapter| adapter.get_info())
+            .collect()
+    }
+    pub async fn build(self) -> Result<WgContext> {
+        let instance = self.instance();
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptionsBase {
-                power_preference: PowerPreference::HighPerformance,
-                force_fallback_adapter: false,
+                power_preference: self.power_preference,
+                force_fallback_adapter: self.force_fallback_adapter,
                 compatible_surface: None,
             })
             .await
-            .unwrap();
+            .ok_or(Error::NoAdapter)?;
         let (device, queue) = adapter
-            .request_device(&Default::default(), None)
-            .await
-            .unwrap();
+            .request_device(
+                &DeviceDescriptor {
+                    label: None,
+                    features: self.features,
+                    limits: self.limits,
+                },
+                None,
+            )
+            .await?;
 
-        Self { device, queue }
+        Ok(WgContext::from_device_queue(
+            Arc::new(device),
+            Arc::new(queue),
+            Some(Arc::new(adapter)),
+        ))
+    }
+    fn instance(&self) -> Instance {
+        Instance::new(InstanceDescriptor {
+            backends: self.backends,
+            ..Default::default()
+        })
     }
 }
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+use wgpu::{
+    util::BufferInitDescriptor, BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource,
+    Buffer, BufferUsages, CommandEncoder, ComputePipeline, TextureFormat,
+};
+
+use super::border::{BorderMode, BORDER_SHADER};
+use super::buffer::WgImageBuffer;
+use super::context::WgContext;
+use super::error::{Error, Result};
+use super::filter::{
+    common_size, descriptors, output_format, BindGroupCache, Filter, ImageCache, ImageDescriptor,
+};
+use super::kernel::{check_kernel_size, create_kernel_buffer, write_kernel_buffer, Kernel};
+use super::utils::{
+    compute_work_group_count, shader_with_output_format, storage_buffer_entry,
+    storage_texture_entry, texture_entry, uniform_entry,
+};
+
+const CONVOLVE_2D_SHADER: &str = include_str!("shaders/convolve_2d.wgsl");
+
+/// Convolves images with an arbitrary `width` x `height` kernel, like OpenCV's
+/// `filter2D`.
+///
+/// The kernel is applied as a correlation centered on each pixel, without
+/// flipping, to all four channels. Prefer `SeparableConvolve` for separable
+/// kernels such as box or Gaussian blurs.
+pub struct Convolve2D {
+    context: WgContext,
+    format: Option<TextureFormat>,
+    pipelines: HashMap<TextureFormat, Arc<ComputePipeline>>,
+    images: ImageCache,
+    /// Constants bind group of each pipeline, reset when the kernel buffer is
+    /// reallocated.
+    constants: HashMap<TextureFormat, BindGroup>,
+    bind_groups: BindGroupCache,
+    settings: Buffer,
+    kernel: Buffer,
+    border: Buffer,
+    kernel_values: Vec<f32>,
+    kernel_size: (u32, u32),
+    border_mode: BorderMode,
+}
+
+impl Convolve2D {
+    /// `kernel` holds `width * height` values in row-major order; both
+    /// dimensions must be odd. Writes outputs in the input format when
+    /// possible, see `with_format`.
+    pub fn new(context: &WgContext, kernel: &[f32], width: u32, height: u32) -> Result<Self> {
+        check_kernel(kernel, width, height)?;
+        let settings = context.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Image info"),
+            contents: bytemuck::cast_slice(&[width, height]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let border_mode = BorderMode::default();
+        let border = context.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Border"),
+            contents: bytemuck::cast_slice(&border_mode.uniform_data()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        Ok(Convolve2D {
+            context: context.clone(),
+            format: None,
+            pipelines: HashMap::new(),
+            images: ImageCache::default(),
+            constants: HashMap::new(),
+            bind_groups: BindGroupCache::default(),
+            settings,
+            kernel: create_kernel_buffer(context, &Kernel::unnormalized(kernel.to_vec())),
+            border,
+            kernel_values: kernel.to_vec(),
+            kernel_size: (width, height),
+            border_mode,
+        })
+    }
+    /// Always writes outputs of `format`.
+    pub fn with_format(
+        context: &WgContext,
+        kernel: &[f32],
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+    ) -> Result<Self> {
+        let mut filter = Self::new(context, kernel, width, height)?;
+        filter.format = Some(format);
+        filter.pipeline(format)?;
+        Ok(filter)
+    }
+    fn pipeline(&mut self, format: TextureFormat) -> Result<&ComputePipeline> {
+        if !self.pipelines.contains_key(&format) {
+            let shader = shader_with_output_format(
+                &self.context,
+                &format!("{}\n{}", BORDER_SHADER, CONVOLVE_2D_SHADER),
+                format,
+            )?;
+            let pipeline = self.context.create_compute_pipeline(
+                "convolve 2d",
+                &shader,
+                "main",
+                &[
+                    &[
+                        uniform_entry(0),
+                        storage_buffer_entry(1, true),
+                        uniform_entry(2),
+                    ],
+                    &[texture_entry(0), storage_texture_entry(1, format)],
+                ],
+            )?;
+            self.pipelines.insert(format, pipeline);
+        }
+        Ok(&self.pipelines[&format])
+    }
+    /// Kernel values in row-major order.
+    pub fn kernel(&self) -> &[f32] {
+        &self.kernel_values
+    }
+    /// Width and height of the kernel.
+    pub fn kernel_size(&self) -> (u32, u32) {
+        self.kernel_size
+    }
+    /// Replaces the kernel for the next submitted run. The kernel buffer is
+    /// only reallocated when the number of values changes.
+    pub fn set_kernel(&mut self, kernel: &[f32], width: u32, height: u32) -> Result<()> {
+        check_kernel(kernel, width, height)?;
+        let packed = Kernel::unnormalized(kernel.to_vec());
+        if write_kernel_buffer(&self.context, &mut self.kernel, &packed) {
+            self.constants.clear();
+        }
+        self.context
+            .queue
+            .write_buffer(&self.settings, 0, bytemuck::cast_slice(&[width, height]));
+        self.kernel_values = kernel.to_vec();
+        self.kernel_size = (width, height);
+        Ok(())
+    }
+    pub fn border_mode(&self) -> BorderMode {
+        self.border_mode
+    }
+    /// Changes how pixels outside the image are read by the next submitted
+    /// run. Defaults to `BorderMode::Reflect101`.
+    pub fn set_border_mode(&mut self, border_mode: BorderMode) {
+        self.border_mode = border_mode;
+        self.context.queue.write_buffer(
+            &self.border,
+            0,
+            bytemuck::cast_slice(&border_mode.uniform_data()),
+        );
+    }
+}
+
+fn check_kernel(kernel: &[f32], width: u32, height: u32) -> Result<()> {
+    check_kernel_size(width as usize)?;
+    check_kernel_size(height as usize)?;
+    let expected = width as usize * height as usize;
+    if kernel.len() != expected {
+        return Err(Error::InvalidKernelLength {
+            expected,
+            actual: kernel.len(),
+        });
+    }
+    Ok(())
+}
+
+impl Filter for Convolve2D {
+    fn context(&self) -> &WgContext {
+        &self.context
+    }
+
+    fn outputs(&self) -> Vec<&WgImageBuffer> {
+        self.images.current().iter().collect()
+    }
+
+    fn output_descriptor(&self, inputs: &[ImageDescriptor]) -> Result<ImageDescriptor> {
+        let (width, height) = common_size(inputs, 1)?;
+        Ok(ImageDescriptor {
+            width,
+            height,
+            format: output_format(&self.context, self.format, inputs[0].format),
+        })
+    }
+
+    fn encode(&mut self, encoder: &mut CommandEncoder, inputs: &[&WgImageBuffer]) -> Result<()> {
+        let descriptor = self.output_descriptor(&descriptors(inputs)?)?;
+        let input_image = inputs[0];
+        self.pipeline(descriptor.format)?;
+        let pipeline = &self.pipelines[&descriptor.format];
+        let output_image = &self.images.get(&self.context, &[descriptor])?[0];
+        let device = &self.context.device;
+        let (settings, kernel, border) = (&self.settings, &self.kernel, &self.border);
+        let compute_constants = self.constants.entry(descriptor.format).or_insert_with(|| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Compute constants"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: settings.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: kernel.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: border.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+        let image_bind_group =
+            self.bind_groups
+                .get_or_insert_with(&[input_image, output_image], || {
+                    device.create_bind_group(&BindGroupDescriptor {
+                        label: Some("Texture bind group"),
+                        layout: &pipeline.get_bind_group_layout(1),
+                        entries: &[
+                            BindGroupEntry {
+                                binding: 0,
+                                resource: BindingResource::TextureView(input_image.view()),
+                            },
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: BindingResource::TextureView(output_image.view()),
+                            },
+                        ],
+                    })
+                });
+        {
+            let (dispatch_width, dispatch_height) = compute_work_group_count(
+                (
+                    input_image.texture_extent.width,
+                    input_image.texture_extent.height,
+                ),
+                (16, 16),
+            );
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, compute_constants, &[]);
+            compute_pass.set_bind_group(1, image_bind_group, &[]);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+        }
+        Ok(())
+    }
+}
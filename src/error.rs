@@ -0,0 +1,116 @@
+use std::fmt;
+
+use wgpu::{BufferAsyncError, RequestDeviceError, TextureFormat};
+
+#[derive(Debug)]
+pub enum Error {
+    /// No adapter matched the requested options.
+    NoAdapter,
+    /// The adapter refused to create a device.
+    RequestDevice(RequestDeviceError),
+    /// A filter received a different number of inputs than it reads.
+    InputCount { expected: usize, actual: usize },
+    /// Two images that must have the same size do not.
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    /// An image has a different texture format than required.
+    FormatMismatch {
+        expected: TextureFormat,
+        actual: TextureFormat,
+    },
+    /// A host buffer does not have the length the image requires.
+    InvalidLength { expected: usize, actual: usize },
+    /// A kernel size is not a positive odd number.
+    InvalidKernelSize(u32),
+    /// A kernel does not have as many values as its size requires.
+    InvalidKernelLength { expected: usize, actual: usize },
+    /// The image is larger than the device allows.
+    ExceedsLimits {
+        width: u32,
+        height: u32,
+        max_dimension: u32,
+    },
+    /// The texture cannot be used as an image buffer.
+    UnsupportedTexture(String),
+    /// A shader module or compute pipeline failed validation.
+    ShaderValidation(String),
+    /// Mapping a buffer for reading failed.
+    BufferMap(BufferAsyncError),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoAdapter => write!(f, "no suitable adapter found"),
+            Error::RequestDevice(error) => write!(f, "failed to request device: {}", error),
+            Error::InputCount { expected, actual } => {
+                write!(f, "expected {} input images, got {}", expected, actual)
+            }
+            Error::SizeMismatch { expected, actual } => write!(
+                f,
+                "image size mismatch: expected {}x{}, got {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            Error::FormatMismatch { expected, actual } => write!(
+                f,
+                "texture format mismatch: expected {:?}, got {:?}",
+                expected, actual
+            ),
+            Error::InvalidLength { expected, actual } => write!(
+                f,
+                "invalid buffer length: expected {} bytes, got {}",
+                expected, actual
+            ),
+            Error::InvalidKernelSize(size) => {
+                write!(f, "kernel size {} is not a positive odd number", size)
+            }
+            Error::InvalidKernelLength { expected, actual } => write!(
+                f,
+                "invalid kernel length: expected {} values, got {}",
+                expected, actual
+            ),
+            Error::ExceedsLimits {
+                width,
+                height,
+                max_dimension,
+            } => write!(
+                f,
+                "image size {}x{} exceeds the device limit of {}",
+                width, height, max_dimension
+            ),
+            Error::UnsupportedTexture(description) => {
+                write!(f, "unsupported texture: {}", description)
+            }
+            Error::ShaderValidation(description) => {
+                write!(f, "shader validation failed: {}", description)
+            }
+            Error::BufferMap(error) => write!(f, "failed to map buffer: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::RequestDevice(error) => Some(error),
+            Error::BufferMap(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<RequestDeviceError> for Error {
+    fn from(error: RequestDeviceError) -> Self {
+        Error::RequestDevice(error)
+    }
+}
+
+impl From<BufferAsyncError> for Error {
+    fn from(error: BufferAsyncError) -> Self {
+        Error::BufferMap(error)
+    }
+}
@@ -0,0 +1,259 @@
+use std::sync::{Arc, Weak};
+
+use wgpu::{
+    BindGroup, CommandEncoder, CommandEncoderDescriptor, TextureFormat, TextureUsages, TextureView,
+};
+
+use super::buffer::WgImageBuffer;
+use super::context::WgContext;
+use super::error::{Error, Result};
+use super::utils::storage_format_name;
+
+/// Size and format of an image read or written by a filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ImageDescriptor {
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+}
+
+/// Common interface of every image filter.
+///
+/// A filter reads `inputs()` images, records its work into a command encoder
+/// and writes the result into the images returned by `outputs()`. Outputs are
+/// allocated on demand for the size of each input, so one filter can process
+/// images of different sizes.
+pub trait Filter {
+    fn context(&self) -> &WgContext;
+
+    /// Number of input images expected by `encode`.
+    fn inputs(&self) -> usize {
+        1
+    }
+
+    /// Images written by the last `encode`, primary output first. Empty until
+    /// the filter has been encoded once.
+    fn outputs(&self) -> Vec<&WgImageBuffer>;
+
+    /// Primary image written by the last `encode`.
+    fn output_image(&self) -> Option<&WgImageBuffer> {
+        self.outputs().first().copied()
+    }
+
+    /// Describes the primary output produced for inputs described by `inputs`,
+    /// failing if they are not valid for this filter.
+    fn output_descriptor(&self, inputs: &[ImageDescriptor]) -> Result<ImageDescriptor>;
+
+    /// Records the filter's passes into `encoder` without submitting them.
+    fn encode(&mut self, encoder: &mut CommandEncoder, inputs: &[&WgImageBuffer]) -> Result<()>;
+
+    /// Records the filter into a fresh encoder and submits it.
+    fn run(&mut self, input_image: &WgImageBuffer) -> Result<()> {
+        let mut encoder = self
+            .context()
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+        self.encode(&mut encoder, &[input_image])?;
+        self.context().queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+/// Checks the number of inputs and that they all have the same size, which is
+/// returned.
+pub(crate) fn common_size(inputs: &[ImageDescriptor], expected: usize) -> Result<(u32, u32)> {
+    if inputs.len() != expected {
+        return Err(Error::InputCount {
+            expected,
+            actual: inputs.len(),
+        });
+    }
+    let size = (inputs[0].width, inputs[0].height);
+    for input in &inputs[1..] {
+        if (input.width, input.height) != size {
+            return Err(Error::SizeMismatch {
+                expected: size,
+                actual: (input.width, input.height),
+            });
+        }
+    }
+    Ok(size)
+}
+
+/// Describes the input images of `encode`, failing if filters cannot read
+/// them.
+pub(crate) fn descriptors(images: &[&WgImageBuffer]) -> Result<Vec<ImageDescriptor>> {
+    images
+        .iter()
+        .map(|image| {
+            image.check_usage(TextureUsages::TEXTURE_BINDING)?;
+            Ok(image.descriptor())
+        })
+        .collect()
+}
+
+/// Picks the requested output format, or the input format if filters can
+/// write it, falling back to `Rgba8Unorm`.
+pub(crate) fn output_format(
+    context: &WgContext,
+    requested: Option<TextureFormat>,
+    input: TextureFormat,
+) -> TextureFormat {
+    requested.unwrap_or(
+        if storage_format_name(input).is_some() && context.supports_storage(input) {
+            input
+        } else {
+            TextureFormat::Rgba8Unorm
+        },
+    )
+}
+
+/// Output format of filters that can write a single channel. When
+/// `single_channel` is set and no format was requested, this is `R8Unorm` if
+/// the device can write it and `R32Float` otherwise, failing if neither is
+/// writable; `output_format` is used in the other cases.
+pub(crate) fn single_channel_format(
+    context: &WgContext,
+    requested: Option<TextureFormat>,
+    single_channel: bool,
+    input: TextureFormat,
+) -> Result<TextureFormat> {
+    if requested.is_some() || !single_channel {
+        return Ok(output_format(context, requested, input));
+    }
+    [TextureFormat::R8Unorm, TextureFormat::R32Float]
+        .into_iter()
+        .find(|format| context.supports_storage(*format))
+        .ok_or_else(|| {
+            Error::UnsupportedTexture("the device cannot write single-channel images".into())
+        })
+}
+
+/// Whether `format` has at least red, green and blue channels.
+pub(crate) fn has_color_channels(format: TextureFormat) -> bool {
+    !matches!(
+        format,
+        TextureFormat::R8Unorm
+            | TextureFormat::R8Snorm
+            | TextureFormat::R16Unorm
+            | TextureFormat::R16Snorm
+            | TextureFormat::R16Float
+            | TextureFormat::R32Float
+            | TextureFormat::Rg8Unorm
+            | TextureFormat::Rg8Snorm
+            | TextureFormat::Rg16Unorm
+            | TextureFormat::Rg16Snorm
+            | TextureFormat::Rg16Float
+            | TextureFormat::Rg32Float
+    )
+}
+
+/// WGSL `vec4<f32>` weights giving the BT.601 luminance of color texels, or
+/// the red channel of others.
+pub(crate) fn luma_weights(color: bool) -> &'static str {
+    if color {
+        "vec4<f32>(0.299, 0.587, 0.114, 0.0)"
+    } else {
+        "vec4<f32>(1.0, 0.0, 0.0, 0.0)"
+    }
+}
+
+/// Most recently used image sets kept by an `ImageCache`.
+const IMAGE_CACHE_CAPACITY: usize = 4;
+
+/// Images owned by a filter, allocated on demand and keyed by their sizes and
+/// formats. A few recently used sets are kept so that alternating between
+/// input sizes does not reallocate every time.
+#[derive(Default)]
+pub(crate) struct ImageCache {
+    entries: Vec<(Vec<ImageDescriptor>, Vec<WgImageBuffer>)>,
+}
+
+impl ImageCache {
+    /// Returns images matching `descriptors`, allocating them if needed, and
+    /// makes them the current set.
+    pub(crate) fn get(
+        &mut self,
+        context: &WgContext,
+        descriptors: &[ImageDescriptor],
+    ) -> Result<&[WgImageBuffer]> {
+        match self.entries.iter().position(|(key, _)| key == descriptors) {
+            Some(index) => {
+                let entry = self.entries.remove(index);
+                self.entries.push(entry);
+            }
+            None => {
+                let images = descriptors
+                    .iter()
+                    .map(|descriptor| {
+                        WgImageBuffer::from_size_with_format(
+                            context,
+                            descriptor.width,
+                            descriptor.height,
+                            descriptor.format,
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                if self.entries.len() == IMAGE_CACHE_CAPACITY {
+                    self.entries.remove(0);
+                }
+                self.entries.push((descriptors.to_vec(), images));
+            }
+        }
+        Ok(self.current())
+    }
+    /// Images returned by the last `get`.
+    pub(crate) fn current(&self) -> &[WgImageBuffer] {
+        self.entries
+            .last()
+            .map(|(_, images)| &images[..])
+            .unwrap_or(&[])
+    }
+}
+
+/// Bind groups kept by a `BindGroupCache`.
+const BIND_GROUP_CACHE_CAPACITY: usize = 16;
+
+/// Bind groups owned by a filter, keyed by the image views they bind.
+///
+/// Entries only hold weak references to the views, and are dropped as soon as
+/// one of their images is, so the cache never keeps a texture alive.
+#[derive(Default)]
+pub(crate) struct BindGroupCache {
+    entries: Vec<(Vec<Weak<TextureView>>, BindGroup)>,
+}
+
+impl BindGroupCache {
+    /// Returns the bind group for `images`, creating it with `create` if
+    /// there is none yet.
+    pub(crate) fn get_or_insert_with(
+        &mut self,
+        images: &[&WgImageBuffer],
+        create: impl FnOnce() -> BindGroup,
+    ) -> &BindGroup {
+        self.entries
+            .retain(|(views, _)| views.iter().all(|view| view.strong_count() > 0));
+        let matches = |views: &[Weak<TextureView>]| {
+            views.len() == images.len()
+                && views
+                    .iter()
+                    .zip(images)
+                    .all(|(view, image)| Weak::as_ptr(view) == Arc::as_ptr(image.view()))
+        };
+        let index = match self.entries.iter().position(|(views, _)| matches(views)) {
+            Some(index) => index,
+            None => {
+                if self.entries.len() == BIND_GROUP_CACHE_CAPACITY {
+                    self.entries.remove(0);
+                }
+                let views = images
+                    .iter()
+                    .map(|image| Arc::downgrade(image.view()))
+                    .collect();
+                self.entries.push((views, create()));
+                self.entries.len() - 1
+            }
+        };
+        &self.entries[index].1
+    }
+}
@@ -1,52 +1,37 @@
-use wgpu::util::DeviceExt;
-use wgpu::{
-    util::BufferInitDescriptor, BindGroupDescriptor, BindGroupEntry, BindingResource, Buffer,
-    BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline,
-    ComputePipelineDescriptor, ShaderModuleDescriptor, ShaderSource, TextureViewDescriptor,
-};
+use wgpu::{CommandEncoder, TextureFormat};
 
+use super::border::BorderMode;
 use super::buffer::WgImageBuffer;
 use super::context::WgContext;
-use super::utils::compute_work_group_count;
+use super::error::{Error, Result};
+use super::filter::{Filter, ImageDescriptor};
+use super::kernel::Kernel;
+use super::separable_convolve::{ConvolutionMethod, SeparableConvolve};
 
-const GAUSSIAN_BLUR_SHADER: &str = include_str!("shaders/gaussian_blur.wgsl");
-
-pub struct GaussianBlur<'a> {
-    vertical_pass_image: WgImageBuffer,
-    pub output_image: WgImageBuffer,
-    context: &'a WgContext,
-    pipeline: ComputePipeline,
-    settings: Buffer,
-    kernel: Buffer,
-    vertical: Buffer,
-    horizontal: Buffer,
-}
-
-struct Kernel {
-    sum: f32,
-    values: Vec<f32>,
+pub struct GaussianBlur {
+    convolve: SeparableConvolve,
+    sigma_x: f32,
+    sigma_y: f32,
+    /// Requested kernel width and height, 0 to derive them from the sigmas.
+    kernel_size: (u32, u32),
 }
 
-impl Kernel {
-    fn new(values: Vec<f32>) -> Self {
-        let sum = values.iter().sum();
-        Self { sum, values }
-    }
-
-    fn packed_data(&self) -> Vec<f32> {
-        let mut data = vec![0.0; self.values.len() + 1];
-        data[0] = self.sum;
-        data[1..].copy_from_slice(&self.values);
-        data
-    }
-
-    fn size(&self) -> usize {
-        self.values.len()
+/// Builds a kernel of `kernel_size` taps, or of `2 * ceil(3 * sigma) + 1`
+/// taps if it is 0. A non-positive sigma is derived from the kernel size the
+/// same way as OpenCV's `getGaussianKernel`.
+pub(crate) fn create_kernel(sigma: f32, kernel_size: u32) -> Kernel {
+    let kernel_size = match kernel_size {
+        0 => 2 * (sigma.max(0.0) * 3.0).ceil() as u32 + 1,
+        size => size,
+    };
+    if kernel_size == 1 {
+        return Kernel::normalized(vec![1.0]);
     }
-}
-
-fn create_kernel(sigma: f32) -> Kernel {
-    let kernel_size = 2 * (sigma * 3.0).ceil() as u32 + 1;
+    let sigma = if sigma > 0.0 {
+        sigma
+    } else {
+        0.3 * ((kernel_size - 1) as f32 * 0.5 - 1.0) + 0.8
+    };
     let mut values = vec![0.0; kernel_size as usize];
     let kernel_radius = (kernel_size as usize - 1) / 2;
     for index in 0..=kernel_radius {
@@ -55,157 +40,108 @@ fn create_kernel(sigma: f32) -> Kernel {
         values[kernel_radius + index] = normpdf;
         values[kernel_radius - index] = normpdf;
     }
-    Kernel::new(values)
+    Kernel::normalized(values)
 }
 
-impl<'a> GaussianBlur<'a> {
-    pub fn new(context: &'a WgContext, width: u32, height: u32, sigma: f32) -> Self {
-        let kernel = create_kernel(sigma);
-        let kernel_size = kernel.size() as u32;
-        let vertical_pass_image = WgImageBuffer::from_size(context, width, height);
-        let horizontal_pass_image = WgImageBuffer::from_size(context, width, height);
-        let shader = context.device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("gaussian blur shader"),
-            source: ShaderSource::Wgsl(GAUSSIAN_BLUR_SHADER.into()),
-        });
-        let pipeline = context
-            .device
-            .create_compute_pipeline(&ComputePipelineDescriptor {
-                label: Some("gaussian blur pipeline"),
-                layout: None,
-                module: &shader,
-                entry_point: "main",
-            });
-        let settings = context.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Image info"),
-            contents: bytemuck::cast_slice(&[kernel_size]),
-            usage: BufferUsages::UNIFORM,
-        });
-        let kernel = context.device.create_buffer_init(&BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&kernel.packed_data()[..]),
-            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
-        });
-        let vertical = context.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Orientation"),
-            contents: bytemuck::cast_slice::<u32, u8>(&[1]),
-            usage: BufferUsages::UNIFORM,
-        });
-        let horizontal = context.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Orientation"),
-            contents: bytemuck::cast_slice::<u32, u8>(&[0]),
-            usage: BufferUsages::UNIFORM,
-        });
-        GaussianBlur {
-            vertical_pass_image,
-            output_image: horizontal_pass_image,
-            context,
-            pipeline,
-            settings,
-            kernel,
-            vertical,
-            horizontal,
-        }
+impl GaussianBlur {
+    /// Writes outputs in the input format when possible, see `with_format`.
+    pub fn new(context: &WgContext, sigma: f32) -> Result<Self> {
+        Self::with_sigmas(context, sigma, sigma)
+    }
+    /// Blurs with `sigma_x` along rows and `sigma_y` along columns.
+    pub fn with_sigmas(context: &WgContext, sigma_x: f32, sigma_y: f32) -> Result<Self> {
+        Ok(GaussianBlur {
+            convolve: SeparableConvolve::from_kernels(
+                context,
+                &create_kernel(sigma_x, 0),
+                &create_kernel(sigma_y, 0),
+            ),
+            sigma_x,
+            sigma_y,
+            kernel_size: (0, 0),
+        })
+    }
+    /// Always writes outputs of `format`; the intermediate vertical pass uses
+    /// the same format so float outputs keep their precision.
+    pub fn with_format(context: &WgContext, sigma: f32, format: TextureFormat) -> Result<Self> {
+        let mut filter = Self::new(context, sigma)?;
+        filter.convolve.set_format(format)?;
+        Ok(filter)
+    }
+    /// Horizontal sigma, equal to the vertical one unless built by
+    /// `with_sigmas`.
+    pub fn sigma(&self) -> f32 {
+        self.sigma_x
+    }
+    pub fn sigmas(&self) -> (f32, f32) {
+        (self.sigma_x, self.sigma_y)
+    }
+    /// Changes both sigmas used by the next submitted run. The kernel
+    /// buffers are only reallocated when the kernel sizes change.
+    pub fn set_sigma(&mut self, sigma: f32) {
+        self.set_sigmas(sigma, sigma);
     }
-    pub fn run(&mut self, input_image: &WgImageBuffer) {
-        let compute_constants = self.context.device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Compute constants"),
-            layout: &self.pipeline.get_bind_group_layout(0),
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: self.settings.as_entire_binding(),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: self.kernel.as_entire_binding(),
-                },
-            ],
-        });
-        let vertical_bind_group = self.context.device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Texture bind group"),
-            layout: &self.pipeline.get_bind_group_layout(1),
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(
-                        &input_image
-                            .texture
-                            .create_view(&TextureViewDescriptor::default()),
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::TextureView(
-                        &self
-                            .vertical_pass_image
-                            .texture
-                            .create_view(&TextureViewDescriptor::default()),
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: self.vertical.as_entire_binding(),
-                },
-            ],
-        });
-        let horizontal_bind_group = self.context.device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Texture bind group"),
-            layout: &self.pipeline.get_bind_group_layout(1),
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(
-                        &self
-                            .vertical_pass_image
-                            .texture
-                            .create_view(&TextureViewDescriptor::default()),
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::TextureView(
-                        &self
-                            .output_image
-                            .texture
-                            .create_view(&TextureViewDescriptor::default()),
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: self.horizontal.as_entire_binding(),
-                },
-            ],
-        });
-        let mut encoder = self
-            .context
-            .device
-            .create_command_encoder(&CommandEncoderDescriptor { label: None });
-        {
-            let mut compute_pass =
-                encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
-            compute_pass.set_pipeline(&self.pipeline);
-            compute_pass.set_bind_group(0, &compute_constants, &[]);
-            compute_pass.set_bind_group(1, &vertical_bind_group, &[]);
-            let (dispatch_with, dispatch_height) = compute_work_group_count(
-                (
-                    input_image.texture_extent.width,
-                    input_image.texture_extent.height,
-                ),
-                (128, 1),
-            );
-            compute_pass.dispatch_workgroups(dispatch_with, dispatch_height, 1);
-            compute_pass.set_bind_group(1, &horizontal_bind_group, &[]);
-            let (dispatch_height, dispatch_with) = compute_work_group_count(
-                (
-                    input_image.texture_extent.width,
-                    input_image.texture_extent.height,
-                ),
-                (1, 128),
-            );
-            compute_pass.dispatch_workgroups(dispatch_with, dispatch_height, 1);
+    pub fn set_sigmas(&mut self, sigma_x: f32, sigma_y: f32) {
+        self.sigma_x = sigma_x;
+        self.sigma_y = sigma_y;
+        self.update_kernels();
+    }
+    /// Width and height of the kernels in use.
+    pub fn kernel_size(&self) -> (u32, u32) {
+        self.convolve.kernel_size()
+    }
+    /// Overrides the kernel width and height, which must be odd, like
+    /// OpenCV's `ksize`. A size of 0 is derived from the sigma of its axis,
+    /// and a sigma of 0 or less from the kernel size.
+    pub fn set_kernel_size(&mut self, width: u32, height: u32) -> Result<()> {
+        for size in [width, height] {
+            if size != 0 && size % 2 == 0 {
+                return Err(Error::InvalidKernelSize(size));
+            }
         }
+        self.kernel_size = (width, height);
+        self.update_kernels();
+        Ok(())
+    }
+    fn update_kernels(&mut self) {
+        let (width, height) = self.kernel_size;
+        self.convolve.set_packed_kernels(
+            &create_kernel(self.sigma_x, width),
+            &create_kernel(self.sigma_y, height),
+        );
+    }
+    pub fn method(&self) -> ConvolutionMethod {
+        self.convolve.method()
+    }
+    /// Chooses between the direct and tiled shaders. Both produce the same
+    /// output; the default picks the faster one for the kernel size.
+    pub fn set_method(&mut self, method: ConvolutionMethod) {
+        self.convolve.set_method(method);
+    }
+    pub fn border_mode(&self) -> BorderMode {
+        self.convolve.border_mode()
+    }
+    /// Changes how pixels outside the image are read by the next submitted
+    /// run. Defaults to `BorderMode::Reflect101`.
+    pub fn set_border_mode(&mut self, border_mode: BorderMode) {
+        self.convolve.set_border_mode(border_mode);
+    }
+}
+
+impl Filter for GaussianBlur {
+    fn context(&self) -> &WgContext {
+        self.convolve.context()
+    }
+
+    fn outputs(&self) -> Vec<&WgImageBuffer> {
+        self.convolve.outputs()
+    }
+
+    fn output_descriptor(&self, inputs: &[ImageDescriptor]) -> Result<ImageDescriptor> {
+        self.convolve.output_descriptor(inputs)
+    }
 
-        self.context.queue.submit(Some(encoder.finish()));
+    fn encode(&mut self, encoder: &mut CommandEncoder, inputs: &[&WgImageBuffer]) -> Result<()> {
+        self.convolve.encode(encoder, inputs)
     }
 }
@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+use wgpu::{
+    util::BufferInitDescriptor, BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource,
+    Buffer, BufferUsages, CommandEncoder, ComputePipeline, TextureFormat,
+};
+
+use super::border::{BorderMode, BORDER_SHADER};
+use super::buffer::WgImageBuffer;
+use super::context::WgContext;
+use super::error::{Error, Result};
+use super::filter::{
+    common_size, descriptors, has_color_channels, luma_weights, BindGroupCache, Filter, ImageCache,
+    ImageDescriptor,
+};
+use super::kernel::{create_kernel_buffer, write_kernel_buffer, Kernel};
+use super::utils::{
+    compute_work_group_count, shader_with_output_format, storage_buffer_entry, storage_format_name,
+    storage_texture_entry, texture_entry, uniform_entry,
+};
+
+const GRADIENT_SHADER: &str = include_str!("shaders/gradient.wgsl");
+
+/// Derivative kernels used by `Gradient`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GradientOperator {
+    /// 3x3 Sobel.
+    #[default]
+    Sobel3,
+    /// 5x5 Sobel.
+    Sobel5,
+    /// 3x3 Scharr, more rotation invariant than Sobel.
+    Scharr,
+    /// 3x3 Prewitt.
+    Prewitt,
+}
+
+impl GradientOperator {
+    /// Smoothing and derivative factors of the separable kernel.
+    fn factors(self) -> (&'static [f32], &'static [f32]) {
+        match self {
+            GradientOperator::Sobel3 => (&[1.0, 2.0, 1.0], &[-1.0, 0.0, 1.0]),
+            GradientOperator::Sobel5 => (&[1.0, 4.0, 6.0, 4.0, 1.0], &[-1.0, -2.0, 0.0, 2.0, 1.0]),
+            GradientOperator::Scharr => (&[3.0, 10.0, 3.0], &[-1.0, 0.0, 1.0]),
+            GradientOperator::Prewitt => (&[1.0, 1.0, 1.0], &[-1.0, 0.0, 1.0]),
+        }
+    }
+
+    /// Horizontal then vertical kernel, as read by `gradient.wgsl`.
+    fn kernel(self) -> Kernel {
+        let (smooth, derivative) = self.factors();
+        let horizontal = smooth
+            .iter()
+            .flat_map(|s| derivative.iter().map(move |d| s * d));
+        let vertical = derivative
+            .iter()
+            .flat_map(|d| smooth.iter().map(move |s| s * d));
+        Kernel::unnormalized(horizontal.chain(vertical).collect())
+    }
+
+    fn kernel_size(self) -> u32 {
+        self.factors().0.len() as u32
+    }
+
+    /// Response to a step from 0 to 1, the largest derivative it produces.
+    fn max_response(self) -> f32 {
+        let (smooth, derivative) = self.factors();
+        let positive: f32 = derivative.iter().filter(|d| **d > 0.0).sum();
+        smooth.iter().sum::<f32>() * positive
+    }
+}
+
+/// How `Gradient` scales its outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GradientNormalization {
+    /// Raw derivatives in the red and green channels of `Rgba32Float`,
+    /// magnitudes and angles in radians in `R32Float`.
+    #[default]
+    None,
+    /// Like `None` with derivatives divided by the operator's response to a
+    /// unit step, so they lie in [-1, 1].
+    Unit,
+    /// For visualization: `Unit` derivatives mapped to [0, 1] in the red and
+    /// green channels of `Rgba8Unorm` images, the magnitude as gray levels
+    /// and the angle as a fraction of a turn.
+    Rgba8,
+}
+
+impl GradientNormalization {
+    /// Default gradient format and format of the magnitude and angle images.
+    fn formats(self) -> (TextureFormat, TextureFormat) {
+        match self {
+            GradientNormalization::Rgba8 => (TextureFormat::Rgba8Unorm, TextureFormat::Rgba8Unorm),
+            _ => (TextureFormat::Rgba32Float, TextureFormat::R32Float),
+        }
+    }
+}
+
+/// Image gradients of the luminance of the input.
+///
+/// The primary output holds the horizontal derivative in its red channel and
+/// the vertical one in its green channel. Magnitude and angle images are
+/// written as well when enabled. Inputs with fewer than three channels are
+/// differentiated on their red channel, others on their BT.601 luminance.
+pub struct Gradient {
+    context: WgContext,
+    format: Option<TextureFormat>,
+    /// Pipelines by gradient and scalar output formats and whether the input
+    /// has color channels.
+    pipelines: HashMap<PipelineKey, Arc<ComputePipeline>>,
+    /// Gradient, magnitude and angle images; disabled outputs are 1x1.
+    images: ImageCache,
+    constants: HashMap<PipelineKey, BindGroup>,
+    bind_groups: BindGroupCache,
+    settings: Buffer,
+    kernel: Buffer,
+    border: Buffer,
+    operator: GradientOperator,
+    normalization: GradientNormalization,
+    magnitude: bool,
+    angle: bool,
+    border_mode: BorderMode,
+    /// Whether inputs have color channels, derived from their format when
+    /// unset.
+    color_channels: Option<bool>,
+}
+
+type PipelineKey = (TextureFormat, TextureFormat, bool);
+
+impl Gradient {
+    /// Writes derivatives as `Rgba32Float`, readable as `Rgba<f32>` pixels,
+    /// or, once normalized for visualization, `Rgba8Unorm`; see
+    /// `with_format`.
+    pub fn new(context: &WgContext, operator: GradientOperator) -> Result<Self> {
+        let settings = context.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Image info"),
+            contents: &[0; 32],
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let border_mode = BorderMode::default();
+        let border = context.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Border"),
+            contents: bytemuck::cast_slice(&border_mode.uniform_data()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let gradient = Gradient {
+            context: context.clone(),
+            format: None,
+            pipelines: HashMap::new(),
+            images: ImageCache::default(),
+            constants: HashMap::new(),
+            bind_groups: BindGroupCache::default(),
+            settings,
+            kernel: create_kernel_buffer(context, &operator.kernel()),
+            border,
+            operator,
+            normalization: GradientNormalization::default(),
+            magnitude: false,
+            angle: false,
+            border_mode,
+            color_channels: None,
+        };
+        gradient.write_settings();
+        Ok(gradient)
+    }
+    /// Always writes derivatives in the red and green channels of `format`,
+    /// e.g. `Rg32Float` on devices that can write it.
+    pub fn with_format(
+        context: &WgContext,
+        operator: GradientOperator,
+        format: TextureFormat,
+    ) -> Result<Self> {
+        let mut gradient = Self::new(context, operator)?;
+        gradient.format = Some(format);
+        gradient.pipeline(true)?;
+        Ok(gradient)
+    }
+    fn formats(&self) -> (TextureFormat, TextureFormat) {
+        let (format, scalar_format) = self.normalization.formats();
+        (self.format.unwrap_or(format), scalar_format)
+    }
+    fn pipeline_key(&self, color: bool) -> PipelineKey {
+        let (format, scalar_format) = self.formats();
+        (format, scalar_format, color)
+    }
+    fn pipeline(&mut self, color: bool) -> Result<&ComputePipeline> {
+        let key = self.pipeline_key(color);
+        if !self.pipelines.contains_key(&key) {
+            let (format, scalar_format, _) = key;
+            let scalar_format_name =
+                shader_with_output_format(&self.context, "OUTPUT_FORMAT", scalar_format)?;
+            let source = format!("{}\n{}", BORDER_SHADER, GRADIENT_SHADER)
+                .replace("LUMA_WEIGHTS", luma_weights(color))
+                .replace("SCALAR_FORMAT", &scalar_format_name);
+            let shader = shader_with_output_format(&self.context, &source, format)?;
+            let pipeline = self.context.create_compute_pipeline(
+                "gradient",
+                &shader,
+                "main",
+                &[
+                    &[
+                        uniform_entry(0),
+                        storage_buffer_entry(1, true),
+                        uniform_entry(2),
+                    ],
+                    &[
+                        texture_entry(0),
+                        storage_texture_entry(1, format),
+                        storage_texture_entry(2, scalar_format),
+                        storage_texture_entry(3, scalar_format),
+                    ],
+                ],
+            )?;
+            self.pipelines.insert(key, pipeline);
+        }
+        Ok(&self.pipelines[&key])
+    }
+    fn write_settings(&self) {
+        let scale = match self.normalization {
+            GradientNormalization::None => 1.0,
+            _ => 1.0 / self.operator.max_response(),
+        };
+        let normalization = match self.normalization {
+            GradientNormalization::None => 0,
+            GradientNormalization::Unit => 1,
+            GradientNormalization::Rgba8 => 2,
+        };
+        let settings = [
+            self.operator.kernel_size(),
+            normalization,
+            self.magnitude as u32,
+            self.angle as u32,
+            f32::to_bits(scale),
+        ];
+        self.context
+            .queue
+            .write_buffer(&self.settings, 0, bytemuck::cast_slice(&settings));
+    }
+    pub fn operator(&self) -> GradientOperator {
+        self.operator
+    }
+    pub fn set_operator(&mut self, operator: GradientOperator) {
+        self.operator = operator;
+        if write_kernel_buffer(&self.context, &mut self.kernel, &operator.kernel()) {
+            self.constants.clear();
+        }
+        self.write_settings();
+    }
+    pub fn normalization(&self) -> GradientNormalization {
+        self.normalization
+    }
+    pub fn set_normalization(&mut self, normalization: GradientNormalization) {
+        self.normalization = normalization;
+        self.write_settings();
+    }
+    /// Also writes the gradient magnitude, see `magnitude_image`.
+    pub fn set_magnitude_output(&mut self, enabled: bool) {
+        self.magnitude = enabled;
+        self.write_settings();
+    }
+    /// Also writes the gradient angle, see `angle_image`.
+    pub fn set_angle_output(&mut self, enabled: bool) {
+        self.angle = enabled;
+        self.write_settings();
+    }
+    /// Magnitude written by the last `encode`, if enabled.
+    pub fn magnitude_image(&self) -> Option<&WgImageBuffer> {
+        self.images.current().get(1).filter(|_| self.magnitude)
+    }
+    /// Angle in [0, 2π) written by the last `encode`, if enabled.
+    pub fn angle_image(&self) -> Option<&WgImageBuffer> {
+        self.images.current().get(2).filter(|_| self.angle)
+    }
+    pub fn border_mode(&self) -> BorderMode {
+        self.border_mode
+    }
+    /// Overrides whether inputs are differentiated on their luminance, e.g.
+    /// for float copies of single-channel images.
+    pub(crate) fn set_color_channels(&mut self, color_channels: Option<bool>) {
+        self.color_channels = color_channels;
+    }
+    /// Changes how pixels outside the image are read by the next submitted
+    /// run. Defaults to `BorderMode::Reflect101`.
+    pub fn set_border_mode(&mut self, border_mode: BorderMode) {
+        self.border_mode = border_mode;
+        self.context.queue.write_buffer(
+            &self.border,
+            0,
+            bytemuck::cast_slice(&border_mode.uniform_data()),
+        );
+    }
+    /// Descriptors of the gradient, magnitude and angle images.
+    fn image_descriptors(&self, descriptor: ImageDescriptor) -> [ImageDescriptor; 3] {
+        let (_, scalar_format) = self.formats();
+        let scalar = |enabled: bool| ImageDescriptor {
+            width: if enabled { descriptor.width } else { 1 },
+            height: if enabled { descriptor.height } else { 1 },
+            format: scalar_format,
+        };
+        [descriptor, scalar(self.magnitude), scalar(self.angle)]
+    }
+}
+
+impl Filter for Gradient {
+    fn context(&self) -> &WgContext {
+        &self.context
+    }
+
+    fn outputs(&self) -> Vec<&WgImageBuffer> {
+        let images = self.images.current();
+        let enabled = [true, self.magnitude, self.angle];
+        images
+            .iter()
+            .zip(enabled)
+            .filter_map(|(image, enabled)| enabled.then_some(image))
+            .collect()
+    }
+
+    fn output_descriptor(&self, inputs: &[ImageDescriptor]) -> Result<ImageDescriptor> {
+        let (width, height) = common_size(inputs, 1)?;
+        let (format, _) = self.formats();
+        if storage_format_name(format).is_none() || !self.context.supports_storage(format) {
+            return Err(Error::UnsupportedTexture(format!(
+                "{:?} cannot be written by compute shaders on this device",
+                format
+            )));
+        }
+        Ok(ImageDescriptor {
+            width,
+            height,
+            format,
+        })
+    }
+
+    fn encode(&mut self, encoder: &mut CommandEncoder, inputs: &[&WgImageBuffer]) -> Result<()> {
+        let descriptor = self.output_descriptor(&descriptors(inputs)?)?;
+        let input_image = inputs[0];
+        let color = self
+            .color_channels
+            .unwrap_or_else(|| has_color_channels(input_image.format()));
+        let key = self.pipeline_key(color);
+        self.pipeline(color)?;
+        let pipeline = &self.pipelines[&key];
+        let image_descriptors = self.image_descriptors(descriptor);
+        let images = self.images.get(&self.context, &image_descriptors)?;
+        let device = &self.context.device;
+        let (settings, kernel, border) = (&self.settings, &self.kernel, &self.border);
+        let compute_constants = self.constants.entry(key).or_insert_with(|| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Compute constants"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: settings.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: kernel.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: border.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+        let image_bind_group = self.bind_groups.get_or_insert_with(
+            &[input_image, &images[0], &images[1], &images[2]],
+            || {
+                device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("Texture bind group"),
+                    layout: &pipeline.get_bind_group_layout(1),
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(input_image.view()),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::TextureView(images[0].view()),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: BindingResource::TextureView(images[1].view()),
+                        },
+                        BindGroupEntry {
+                            binding: 3,
+                            resource: BindingResource::TextureView(images[2].view()),
+                        },
+                    ],
+                })
+            },
+        );
+        {
+            let (dispatch_width, dispatch_height) = compute_work_group_count(
+                (
+                    input_image.texture_extent.width,
+                    input_image.texture_extent.height,
+                ),
+                (16, 16),
+            );
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, compute_constants, &[]);
+            compute_pass.set_bind_group(1, image_bind_group, &[]);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+        }
+        Ok(())
+    }
+}
@@ -1,69 +1,234 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
 use wgpu::{
-    BindGroupDescriptor, BindGroupEntry, BindingResource, ComputePipeline,
-    ComputePipelineDescriptor, ShaderModuleDescriptor, ShaderSource, TextureViewDescriptor,
+    util::BufferInitDescriptor, BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource,
+    Buffer, BufferUsages, CommandEncoder, ComputePipeline, TextureFormat,
 };
 
 use super::buffer::WgImageBuffer;
 use super::context::WgContext;
-use super::utils::compute_work_group_count;
+use super::error::Result;
+use super::filter::{
+    common_size, descriptors, has_color_channels, single_channel_format, BindGroupCache, Filter,
+    ImageCache, ImageDescriptor,
+};
+use super::utils::{
+    compute_work_group_count, shader_with_output_format, storage_texture_entry, texture_entry,
+    uniform_entry,
+};
 
 const GRAYSCALE_SHADER: &str = include_str!("shaders/grayscale.wgsl");
 
-pub struct GrayScale<'a> {
-    pub output_image: WgImageBuffer,
-    context: &'a WgContext,
-    pipeline: ComputePipeline,
+/// How `GrayScale` combines the red, green and blue channels.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LumaStandard {
+    /// ITU-R BT.601 luma, as used by JPEG and OpenCV.
+    #[default]
+    Bt601,
+    /// ITU-R BT.709 luma, for HD video and sRGB content.
+    Bt709,
+    /// ITU-R BT.2020 luma, for UHD video.
+    Bt2020,
+    /// Mean of the three channels.
+    Average,
+    /// Mean of the largest and smallest channels, the L of HSL.
+    Lightness,
+    /// Red, green and blue weights, applied as given.
+    Custom([f32; 3]),
+}
+
+impl LumaStandard {
+    /// Red, green and blue weights of the standards based on a weighted sum.
+    pub fn weights(self) -> Option<[f32; 3]> {
+        match self {
+            LumaStandard::Bt601 => Some([0.299, 0.587, 0.114]),
+            LumaStandard::Bt709 => Some([0.2126, 0.7152, 0.0722]),
+            LumaStandard::Bt2020 => Some([0.2627, 0.6780, 0.0593]),
+            LumaStandard::Average => Some([1.0 / 3.0; 3]),
+            LumaStandard::Lightness => None,
+            LumaStandard::Custom(weights) => Some(weights),
+        }
+    }
 }
 
-impl<'a> GrayScale<'a> {
-    pub fn new(context: &'a WgContext, width: u32, height: u32) -> Self {
-        let output_image = WgImageBuffer::from_size(context, width, height);
-        let shader = context.device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("grayscale shader"),
-            source: ShaderSource::Wgsl(GRAYSCALE_SHADER.into()),
+/// Replaces colors by their gray level. Inputs without color channels are
+/// gray already, and their red channel is copied as is.
+pub struct GrayScale {
+    context: WgContext,
+    format: Option<TextureFormat>,
+    /// Pipelines by output format and whether the input has color channels.
+    pipelines: HashMap<(TextureFormat, bool), Arc<ComputePipeline>>,
+    images: ImageCache,
+    /// Settings bind group of each pipeline.
+    constants: HashMap<(TextureFormat, bool), BindGroup>,
+    bind_groups: BindGroupCache,
+    settings: Buffer,
+    standard: LumaStandard,
+    linear_light: bool,
+    single_channel: bool,
+}
+
+impl GrayScale {
+    /// Writes outputs in the input format when possible, see `with_format`.
+    pub fn new(context: &WgContext) -> Result<Self> {
+        let settings = context.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Image info"),
+            contents: &[0; 32],
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
-        let pipeline = context
-            .device
-            .create_compute_pipeline(&ComputePipelineDescriptor {
-                label: Some("grayscale pipeline"),
-                layout: None,
-                module: &shader,
-                entry_point: "main",
-            });
-        GrayScale {
-            output_image,
-            context,
-            pipeline,
+        let grayscale = GrayScale {
+            context: context.clone(),
+            format: None,
+            pipelines: HashMap::new(),
+            images: ImageCache::default(),
+            constants: HashMap::new(),
+            bind_groups: BindGroupCache::default(),
+            settings,
+            standard: LumaStandard::default(),
+            linear_light: false,
+            single_channel: false,
+        };
+        grayscale.write_settings();
+        Ok(grayscale)
+    }
+    /// Always writes outputs of `format`.
+    pub fn with_format(context: &WgContext, format: TextureFormat) -> Result<Self> {
+        let mut grayscale = Self::new(context)?;
+        grayscale.format = Some(format);
+        grayscale.pipeline(format, true)?;
+        Ok(grayscale)
+    }
+    fn pipeline(&mut self, format: TextureFormat, color: bool) -> Result<&ComputePipeline> {
+        let key = (format, color);
+        if !self.pipelines.contains_key(&key) {
+            let source = GRAYSCALE_SHADER.replace("COLOR_CHANNELS", &color.to_string());
+            let shader = shader_with_output_format(&self.context, &source, format)?;
+            let pipeline = self.context.create_compute_pipeline(
+                "grayscale",
+                &shader,
+                "main",
+                &[
+                    &[uniform_entry(0)],
+                    &[texture_entry(0), storage_texture_entry(1, format)],
+                ],
+            )?;
+            self.pipelines.insert(key, pipeline);
         }
+        Ok(&self.pipelines[&key])
+    }
+    fn write_settings(&self) {
+        let [red, green, blue] = self.standard.weights().unwrap_or_default();
+        let method = (self.standard == LumaStandard::Lightness) as u32;
+        let settings = [
+            f32::to_bits(red),
+            f32::to_bits(green),
+            f32::to_bits(blue),
+            0,
+            method,
+            self.linear_light as u32,
+            0,
+            0,
+        ];
+        self.context
+            .queue
+            .write_buffer(&self.settings, 0, bytemuck::cast_slice(&settings));
+    }
+    pub fn standard(&self) -> LumaStandard {
+        self.standard
     }
-    pub fn run(&mut self, input_image: &WgImageBuffer) {
-        let bind_group = self.context.device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Texture bind group"),
-            layout: &self.pipeline.get_bind_group_layout(0),
-            entries: &[
-                BindGroupEntry {
+    /// Changes the luma standard used by the next submitted run. Defaults to
+    /// `LumaStandard::Bt601`.
+    pub fn set_standard(&mut self, standard: LumaStandard) {
+        self.standard = standard;
+        self.write_settings();
+    }
+    pub fn linear_light(&self) -> bool {
+        self.linear_light
+    }
+    /// Combines the channels after decoding the sRGB transfer function, and
+    /// encodes the result again, which gives the relative luminance of the
+    /// BT.709 and BT.2020 standards rather than their luma.
+    pub fn set_linear_light(&mut self, enabled: bool) {
+        self.linear_light = enabled;
+        self.write_settings();
+    }
+    pub fn single_channel(&self) -> bool {
+        self.single_channel
+    }
+    /// Writes a single-channel image instead of replicating the gray level in
+    /// the red, green and blue channels, unless a format was requested.
+    ///
+    /// The image is `R8Unorm` on devices that can write it, which requires
+    /// `Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`, and `R32Float`
+    /// otherwise. Runs fail with `Error::UnsupportedTexture` if neither can
+    /// be written.
+    pub fn set_single_channel(&mut self, enabled: bool) {
+        self.single_channel = enabled;
+    }
+}
+
+impl Filter for GrayScale {
+    fn context(&self) -> &WgContext {
+        &self.context
+    }
+
+    fn outputs(&self) -> Vec<&WgImageBuffer> {
+        self.images.current().iter().collect()
+    }
+
+    fn output_descriptor(&self, inputs: &[ImageDescriptor]) -> Result<ImageDescriptor> {
+        let (width, height) = common_size(inputs, 1)?;
+        Ok(ImageDescriptor {
+            width,
+            height,
+            format: single_channel_format(
+                &self.context,
+                self.format,
+                self.single_channel,
+                inputs[0].format,
+            )?,
+        })
+    }
+
+    fn encode(&mut self, encoder: &mut CommandEncoder, inputs: &[&WgImageBuffer]) -> Result<()> {
+        let descriptor = self.output_descriptor(&descriptors(inputs)?)?;
+        let input_image = inputs[0];
+        let key = (descriptor.format, has_color_channels(input_image.format()));
+        self.pipeline(key.0, key.1)?;
+        let pipeline = &self.pipelines[&key];
+        let output_image = &self.images.get(&self.context, &[descriptor])?[0];
+        let device = &self.context.device;
+        let settings = &self.settings;
+        let compute_constants = self.constants.entry(key).or_insert_with(|| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Compute constants"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[BindGroupEntry {
                     binding: 0,
-                    resource: BindingResource::TextureView(
-                        &input_image
-                            .texture
-                            .create_view(&TextureViewDescriptor::default()),
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::TextureView(
-                        &self
-                            .output_image
-                            .texture
-                            .create_view(&TextureViewDescriptor::default()),
-                    ),
-                },
-            ],
+                    resource: settings.as_entire_binding(),
+                }],
+            })
         });
-        let mut encoder = self
-            .context
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let image_bind_group =
+            self.bind_groups
+                .get_or_insert_with(&[input_image, output_image], || {
+                    device.create_bind_group(&BindGroupDescriptor {
+                        label: Some("Texture bind group"),
+                        layout: &pipeline.get_bind_group_layout(1),
+                        entries: &[
+                            BindGroupEntry {
+                                binding: 0,
+                                resource: BindingResource::TextureView(input_image.view()),
+                            },
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: BindingResource::TextureView(output_image.view()),
+                            },
+                        ],
+                    })
+                });
         {
             let (dispatch_width, dispatch_height) = compute_work_group_count(
                 (
@@ -74,10 +239,11 @@ impl<'a> GrayScale<'a> {
             );
             let mut compute_pass =
                 encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
-            compute_pass.set_pipeline(&self.pipeline);
-            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, compute_constants, &[]);
+            compute_pass.set_bind_group(1, image_bind_group, &[]);
             compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
         }
-        self.context.queue.submit(Some(encoder.finish()));
+        Ok(())
     }
 }
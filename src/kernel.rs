@@ -0,0 +1,71 @@
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{Buffer, BufferUsages};
+
+use super::context::WgContext;
+use super::error::{Error, Result};
+
+/// Convolution weights as uploaded to the `Kernel` storage buffer of the
+/// convolution shaders, which divide the weighted sum by `sum`.
+pub(crate) struct Kernel {
+    pub(crate) sum: f32,
+    pub(crate) values: Vec<f32>,
+}
+
+impl Kernel {
+    /// Kernel normalized by the sum of its values.
+    pub(crate) fn normalized(values: Vec<f32>) -> Self {
+        let sum = values.iter().sum();
+        Self { sum, values }
+    }
+
+    /// Kernel applied as given.
+    pub(crate) fn unnormalized(values: Vec<f32>) -> Self {
+        Self { sum: 1.0, values }
+    }
+
+    pub(crate) fn packed_data(&self) -> Vec<f32> {
+        let mut data = vec![0.0; self.values.len() + 1];
+        data[0] = self.sum;
+        data[1..].copy_from_slice(&self.values);
+        data
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.values.len()
+    }
+}
+
+pub(crate) fn create_kernel_buffer(context: &WgContext, kernel: &Kernel) -> Buffer {
+    context.device.create_buffer_init(&BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&kernel.packed_data()[..]),
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+    })
+}
+
+/// Uploads `kernel` into `buffer`, or into a new buffer if the size changed.
+/// Returns `true` if the buffer was reallocated.
+pub(crate) fn write_kernel_buffer(
+    context: &WgContext,
+    buffer: &mut Buffer,
+    kernel: &Kernel,
+) -> bool {
+    let packed_data = kernel.packed_data();
+    if buffer.size() as usize == std::mem::size_of_val(&packed_data[..]) {
+        context
+            .queue
+            .write_buffer(buffer, 0, bytemuck::cast_slice(&packed_data[..]));
+        false
+    } else {
+        *buffer = create_kernel_buffer(context, kernel);
+        true
+    }
+}
+
+/// Checks that a kernel dimension has a center tap.
+pub(crate) fn check_kernel_size(size: usize) -> Result<()> {
+    if size.is_multiple_of(2) {
+        return Err(Error::InvalidKernelSize(size as u32));
+    }
+    Ok(())
+}
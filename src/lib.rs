@@ -1,13 +1,57 @@
+mod adaptive_threshold;
+mod border;
 mod buffer;
+mod canny;
+mod color_convert;
 mod context;
+mod convolve_2d;
+mod error;
+mod filter;
 mod gaussian_blur;
+mod gradient;
 mod grayscale;
+mod kernel;
+mod pipeline;
+mod pixel;
+mod separable_convolve;
+mod shader_cache;
+mod staging;
 mod threshold;
 mod utils;
 
+pub use self::adaptive_threshold::*;
+pub use self::border::*;
 pub use self::buffer::*;
+pub use self::canny::*;
+pub use self::color_convert::*;
 pub use self::context::*;
+pub use self::convolve_2d::*;
+pub use self::error::*;
+pub use self::filter::*;
 pub use self::gaussian_blur::*;
+pub use self::gradient::*;
 pub use self::grayscale::*;
+pub use self::pipeline::*;
+pub use self::pixel::*;
+pub use self::separable_convolve::*;
+pub use self::shader_cache::*;
+pub use self::staging::*;
 pub use self::threshold::*;
 pub use self::utils::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn assert<T: Send + Sync>() {}
+    assert::<WgContext>();
+    assert::<WgImageBuffer>();
+    assert::<GrayScale>();
+    assert::<Threshold>();
+    assert::<GaussianBlur>();
+    assert::<Convolve2D>();
+    assert::<SeparableConvolve>();
+    assert::<Gradient>();
+    assert::<Canny>();
+    assert::<AdaptiveThreshold>();
+    assert::<ColorConvert>();
+}
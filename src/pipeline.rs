@@ -0,0 +1,82 @@
+use wgpu::{CommandEncoder, CommandEncoderDescriptor};
+
+use super::buffer::WgImageBuffer;
+use super::context::WgContext;
+use super::error::Result;
+use super::filter::{Filter, ImageDescriptor};
+
+/// Chain of filters applied to a single input image.
+///
+/// Every filter reads the primary output of the previous one. All filters are
+/// recorded into one command encoder and submitted together.
+pub struct Pipeline<'a> {
+    context: WgContext,
+    input_image: &'a WgImageBuffer,
+    filters: Vec<Box<dyn Filter + 'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new(context: &WgContext, input_image: &'a WgImageBuffer) -> Self {
+        Pipeline {
+            context: context.clone(),
+            input_image,
+            filters: Vec::new(),
+        }
+    }
+    pub fn then<F: Filter + 'a>(mut self, filter: F) -> Self {
+        self.push(Box::new(filter));
+        self
+    }
+    pub fn push(&mut self, filter: Box<dyn Filter + 'a>) {
+        self.filters.push(filter);
+    }
+    pub fn filters(&self) -> &[Box<dyn Filter + 'a>] {
+        &self.filters
+    }
+    /// Image written by the last filter, or the input if the pipeline is
+    /// empty. `None` until the pipeline has been encoded.
+    pub fn output_image(&self) -> Option<&WgImageBuffer> {
+        match self.filters.last() {
+            Some(filter) => filter.output_image(),
+            None => Some(self.input_image),
+        }
+    }
+    /// Describes the image the last filter will write, validating every
+    /// filter against the output of the previous one.
+    pub fn output_descriptor(&self) -> Result<ImageDescriptor> {
+        self.filters
+            .iter()
+            .try_fold(self.input_image.descriptor(), |input, filter| {
+                filter.output_descriptor(&[input])
+            })
+    }
+    /// Records all filters into `encoder` without submitting them.
+    ///
+    /// The whole chain is validated first, so nothing is recorded if any
+    /// filter rejects its input.
+    pub fn encode(&mut self, encoder: &mut CommandEncoder) -> Result<()> {
+        self.output_descriptor()?;
+        for index in 0..self.filters.len() {
+            let (done, rest) = self.filters.split_at_mut(index);
+            let input_image = match done.last() {
+                Some(previous) => previous
+                    .output_image()
+                    .expect("encoded filters have an output image"),
+                None => self.input_image,
+            };
+            rest[0].encode(encoder, &[input_image])?;
+        }
+        Ok(())
+    }
+    pub fn run(&mut self) -> Result<()> {
+        let mut encoder = self
+            .context
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("pipeline encoder"),
+            });
+        self.encode(&mut encoder)?;
+        self.context.queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use std::borrow::Cow;
+
+use half::f16;
+use image::{Luma, LumaA, Pixel, Rgb, Rgba};
+use wgpu::TextureFormat;
+
+/// Pixel types that can be uploaded to and read back from a `WgImageBuffer`.
+///
+/// Each pixel type maps to the texture format it is stored in. Integer
+/// formats without a matching storage format are normalized to `[0, 1]`
+/// floats on upload and converted back on readback.
+pub trait HostPixel: Pixel {
+    const FORMAT: TextureFormat;
+
+    /// Converts subpixels into the texel bytes of `FORMAT`, borrowing them
+    /// when they are already laid out as texels.
+    fn to_texels(subpixels: &[Self::Subpixel]) -> Cow<'_, [u8]>;
+
+    /// Converts texel bytes of `FORMAT` back into subpixels, reusing the
+    /// allocation when they are already laid out as subpixels.
+    fn from_texels(texels: Vec<u8>) -> Vec<Self::Subpixel>;
+
+    /// Other formats the pixels can be uploaded to and read back from, with
+    /// the conversions of `to_format_texels` and `from_format_texels`.
+    const OTHER_FORMATS: &'static [TextureFormat] = &[];
+
+    /// Converts subpixels into the texel bytes of `format`, which is
+    /// `FORMAT` or one of `OTHER_FORMATS`.
+    fn to_format_texels(format: TextureFormat, subpixels: &[Self::Subpixel]) -> Cow<'_, [u8]> {
+        debug_assert_eq!(format, Self::FORMAT);
+        Self::to_texels(subpixels)
+    }
+
+    /// Converts texel bytes of `format`, which is `FORMAT` or one of
+    /// `OTHER_FORMATS`, back into subpixels.
+    fn from_format_texels(format: TextureFormat, texels: Vec<u8>) -> Vec<Self::Subpixel> {
+        debug_assert_eq!(format, Self::FORMAT);
+        Self::from_texels(texels)
+    }
+
+    /// Whether the pixels can be converted to and from texels of `format`.
+    fn supports_format(format: TextureFormat) -> bool {
+        format == Self::FORMAT || Self::OTHER_FORMATS.contains(&format)
+    }
+}
+
+fn f32_texels<'a>(values: impl Iterator<Item = f32>) -> Cow<'a, [u8]> {
+    Cow::Owned(values.flat_map(f32::to_ne_bytes).collect())
+}
+
+fn texel_f32s(texels: &[u8]) -> impl Iterator<Item = f32> + '_ {
+    texels
+        .chunks_exact(4)
+        .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn unorm16(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16
+}
+
+impl HostPixel for Rgba<u8> {
+    const FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+    fn to_texels(subpixels: &[u8]) -> Cow<'_, [u8]> {
+        Cow::Borrowed(subpixels)
+    }
+
+    fn from_texels(texels: Vec<u8>) -> Vec<u8> {
+        texels
+    }
+}
+
+impl HostPixel for Luma<u8> {
+    const FORMAT: TextureFormat = TextureFormat::R8Unorm;
+
+    fn to_texels(subpixels: &[u8]) -> Cow<'_, [u8]> {
+        Cow::Borrowed(subpixels)
+    }
+
+    fn from_texels(texels: Vec<u8>) -> Vec<u8> {
+        texels
+    }
+}
+
+impl HostPixel for LumaA<u8> {
+    const FORMAT: TextureFormat = TextureFormat::Rg8Unorm;
+
+    fn to_texels(subpixels: &[u8]) -> Cow<'_, [u8]> {
+        Cow::Borrowed(subpixels)
+    }
+
+    fn from_texels(texels: Vec<u8>) -> Vec<u8> {
+        texels
+    }
+}
+
+/// Stored as normalized `R32Float`, which keeps all 16 bits.
+impl HostPixel for Luma<u16> {
+    const FORMAT: TextureFormat = TextureFormat::R32Float;
+
+    fn to_texels(subpixels: &[u16]) -> Cow<'_, [u8]> {
+        f32_texels(subpixels.iter().map(|&v| v as f32 / u16::MAX as f32))
+    }
+
+    fn from_texels(texels: Vec<u8>) -> Vec<u16> {
+        texel_f32s(&texels).map(unorm16).collect()
+    }
+}
+
+/// Stored as normalized `Rgba32Float`, which keeps all 16 bits.
+impl HostPixel for Rgba<u16> {
+    const FORMAT: TextureFormat = TextureFormat::Rgba32Float;
+
+    fn to_texels(subpixels: &[u16]) -> Cow<'_, [u8]> {
+        f32_texels(subpixels.iter().map(|&v| v as f32 / u16::MAX as f32))
+    }
+
+    fn from_texels(texels: Vec<u8>) -> Vec<u16> {
+        texel_f32s(&texels).map(unorm16).collect()
+    }
+}
+
+impl HostPixel for Luma<f32> {
+    const FORMAT: TextureFormat = TextureFormat::R32Float;
+
+    fn to_texels(subpixels: &[f32]) -> Cow<'_, [u8]> {
+        f32_texels(subpixels.iter().copied())
+    }
+
+    fn from_texels(texels: Vec<u8>) -> Vec<f32> {
+        texel_f32s(&texels).collect()
+    }
+}
+
+/// Stored as `Rgba32Float` with an opaque alpha channel.
+impl HostPixel for Rgb<f32> {
+    const FORMAT: TextureFormat = TextureFormat::Rgba32Float;
+
+    fn to_texels(subpixels: &[f32]) -> Cow<'_, [u8]> {
+        f32_texels(
+            subpixels
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 1.0]),
+        )
+    }
+
+    fn from_texels(texels: Vec<u8>) -> Vec<f32> {
+        texel_f32s(&texels)
+            .enumerate()
+            .filter(|(index, _)| index % 4 != 3)
+            .map(|(_, value)| value)
+            .collect()
+    }
+}
+
+/// Also converted to and from `Rgba16Float`, e.g. for HDR images, which keeps
+/// about 11 bits of precision.
+impl HostPixel for Rgba<f32> {
+    const FORMAT: TextureFormat = TextureFormat::Rgba32Float;
+    const OTHER_FORMATS: &'static [TextureFormat] = &[TextureFormat::Rgba16Float];
+
+    fn to_texels(subpixels: &[f32]) -> Cow<'_, [u8]> {
+        f32_texels(subpixels.iter().copied())
+    }
+
+    fn from_texels(texels: Vec<u8>) -> Vec<f32> {
+        texel_f32s(&texels).collect()
+    }
+
+    fn to_format_texels(format: TextureFormat, subpixels: &[f32]) -> Cow<'_, [u8]> {
+        match format {
+            TextureFormat::Rgba16Float => Cow::Owned(
+                subpixels
+                    .iter()
+                    .flat_map(|&value| f16::from_f32(value).to_ne_bytes())
+                    .collect(),
+            ),
+            _ => Self::to_texels(subpixels),
+        }
+    }
+
+    fn from_format_texels(format: TextureFormat, texels: Vec<u8>) -> Vec<f32> {
+        match format {
+            TextureFormat::Rgba16Float => texels
+                .chunks_exact(2)
+                .map(|bytes| f16::from_ne_bytes([bytes[0], bytes[1]]).to_f32())
+                .collect(),
+            _ => Self::from_texels(texels),
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+use wgpu::{
+    util::BufferInitDescriptor, BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource,
+    Buffer, BufferUsages, CommandEncoder, ComputePassDescriptor, ComputePipeline, Device,
+    TextureFormat,
+};
+
+use super::border::{BorderMode, BORDER_SHADER};
+use super::buffer::WgImageBuffer;
+use super::context::WgContext;
+use super::error::Result;
+use super::filter::{
+    common_size, descriptors, output_format, BindGroupCache, Filter, ImageCache, ImageDescriptor,
+};
+use super::kernel::{check_kernel_size, create_kernel_buffer, write_kernel_buffer, Kernel};
+use super::utils::{
+    compute_work_group_count, shader_with_output_format, storage_buffer_entry,
+    storage_texture_entry, texture_entry, uniform_entry,
+};
+
+const SEPARABLE_CONVOLVE_SHADER: &str = include_str!("shaders/separable_convolve.wgsl");
+const SEPARABLE_CONVOLVE_TILED_SHADER: &str = include_str!("shaders/separable_convolve_tiled.wgsl");
+
+/// Kernels with at least this many taps use the tiled shader by default.
+const TILED_MIN_KERNEL_SIZE: u32 = 13;
+/// Largest workgroup tile; 1024 `vec4<f32>` fill the 16 KiB of workgroup
+/// memory guaranteed by WebGPU.
+const MAX_TILE_SIZE: u32 = 1024;
+
+/// How each pass of a separable convolution reads its input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConvolutionMethod {
+    /// `Tiled` for large kernels, `Direct` otherwise.
+    #[default]
+    Auto,
+    /// Loads every tap from the texture.
+    Direct,
+    /// Loads a tile of the input into workgroup memory once and convolves
+    /// from there. Kernels too large for the tile fall back to `Direct`.
+    Tiled,
+}
+
+impl ConvolutionMethod {
+    /// Size of the workgroup tile for `kernel_size`, or `None` for the direct
+    /// shader.
+    fn tile_size(self, kernel_size: u32) -> Option<u32> {
+        let min_kernel_size = match self {
+            ConvolutionMethod::Auto => TILED_MIN_KERNEL_SIZE,
+            ConvolutionMethod::Direct => return None,
+            ConvolutionMethod::Tiled => 1,
+        };
+        let tile_size = (127 + kernel_size).next_power_of_two().max(256);
+        (kernel_size >= min_kernel_size && tile_size <= MAX_TILE_SIZE).then_some(tile_size)
+    }
+}
+
+/// Convolves images with a row kernel and then a column kernel, like OpenCV's
+/// `sepFilter2D`.
+///
+/// Kernels are applied as correlations centered on each pixel, without
+/// flipping, to all four channels.
+pub struct SeparableConvolve {
+    context: WgContext,
+    format: Option<TextureFormat>,
+    /// Pipelines by output format and tile size, `None` for the direct shader.
+    pipelines: HashMap<(TextureFormat, Option<u32>), Arc<ComputePipeline>>,
+    /// Vertical pass image followed by the output image.
+    images: ImageCache,
+    vertical: ConvolutionPass,
+    horizontal: ConvolutionPass,
+    border: Buffer,
+    border_mode: BorderMode,
+    method: ConvolutionMethod,
+}
+
+/// Kernel and settings of one of the two separable passes.
+struct ConvolutionPass {
+    vertical: bool,
+    kernel_size: u32,
+    tile_size: Option<u32>,
+    settings: Buffer,
+    kernel: Buffer,
+    /// Constants bind group of each pipeline, reset when the kernel buffer is
+    /// reallocated or the pass switches to another pipeline.
+    constants: HashMap<TextureFormat, BindGroup>,
+    bind_groups: BindGroupCache,
+}
+
+impl ConvolutionPass {
+    fn new(
+        context: &WgContext,
+        kernel: &Kernel,
+        method: ConvolutionMethod,
+        vertical: bool,
+    ) -> Self {
+        let kernel_size = kernel.size() as u32;
+        let settings = context.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Image info"),
+            contents: bytemuck::cast_slice(&[kernel_size, vertical as u32]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        ConvolutionPass {
+            vertical,
+            kernel_size,
+            tile_size: method.tile_size(kernel_size),
+            settings,
+            kernel: create_kernel_buffer(context, kernel),
+            constants: HashMap::new(),
+            bind_groups: BindGroupCache::default(),
+        }
+    }
+    /// Uploads `kernel`, only reallocating the kernel buffer when its size
+    /// changes.
+    fn set_kernel(&mut self, context: &WgContext, kernel: &Kernel, method: ConvolutionMethod) {
+        if write_kernel_buffer(context, &mut self.kernel, kernel) {
+            self.constants.clear();
+        }
+        self.kernel_size = kernel.size() as u32;
+        self.set_method(method);
+        context.queue.write_buffer(
+            &self.settings,
+            0,
+            bytemuck::cast_slice(&[self.kernel_size, self.vertical as u32]),
+        );
+    }
+    /// Picks the pipeline for the current kernel size, dropping the bind
+    /// groups created for the previous one.
+    fn set_method(&mut self, method: ConvolutionMethod) {
+        let tile_size = method.tile_size(self.kernel_size);
+        if tile_size != self.tile_size {
+            self.tile_size = tile_size;
+            self.constants.clear();
+            self.bind_groups = BindGroupCache::default();
+        }
+    }
+    /// Constants and image bind groups reading `source` and writing
+    /// `destination`.
+    fn bind_groups(
+        &mut self,
+        device: &Device,
+        pipeline: &ComputePipeline,
+        format: TextureFormat,
+        border: &Buffer,
+        source: &WgImageBuffer,
+        destination: &WgImageBuffer,
+    ) -> (&BindGroup, &BindGroup) {
+        let (settings, kernel) = (&self.settings, &self.kernel);
+        let constants = self.constants.entry(format).or_insert_with(|| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Compute constants"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: settings.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: kernel.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: border.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+        let images = self
+            .bind_groups
+            .get_or_insert_with(&[source, destination], || {
+                device.create_bind_group(&BindGroupDescriptor {
+                    label: Some("Texture bind group"),
+                    layout: &pipeline.get_bind_group_layout(1),
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(source.view()),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::TextureView(destination.view()),
+                        },
+                    ],
+                })
+            });
+        (constants, images)
+    }
+    fn dispatch_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let (along, across) = if self.vertical {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        if self.tile_size.is_some() {
+            compute_work_group_count((along, across), (128, 1))
+        } else {
+            compute_work_group_count((across, along), (128, 1))
+        }
+    }
+}
+
+impl SeparableConvolve {
+    /// Writes outputs in the input format when possible, see `with_format`.
+    /// Both kernels must have an odd length.
+    pub fn new(context: &WgContext, row_kernel: &[f32], column_kernel: &[f32]) -> Result<Self> {
+        check_kernel_size(row_kernel.len())?;
+        check_kernel_size(column_kernel.len())?;
+        Ok(Self::from_kernels(
+            context,
+            &Kernel::unnormalized(row_kernel.to_vec()),
+            &Kernel::unnormalized(column_kernel.to_vec()),
+        ))
+    }
+    /// Always writes outputs of `format`; the intermediate vertical pass uses
+    /// the same format so float outputs keep their precision.
+    pub fn with_format(
+        context: &WgContext,
+        row_kernel: &[f32],
+        column_kernel: &[f32],
+        format: TextureFormat,
+    ) -> Result<Self> {
+        let mut filter = Self::new(context, row_kernel, column_kernel)?;
+        filter.set_format(format)?;
+        Ok(filter)
+    }
+    pub(crate) fn from_kernels(
+        context: &WgContext,
+        row_kernel: &Kernel,
+        column_kernel: &Kernel,
+    ) -> Self {
+        let border_mode = BorderMode::default();
+        let border = context.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Border"),
+            contents: bytemuck::cast_slice(&border_mode.uniform_data()),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let method = ConvolutionMethod::default();
+        SeparableConvolve {
+            context: context.clone(),
+            format: None,
+            pipelines: HashMap::new(),
+            images: ImageCache::default(),
+            vertical: ConvolutionPass::new(context, column_kernel, method, true),
+            horizontal: ConvolutionPass::new(context, row_kernel, method, false),
+            border,
+            border_mode,
+            method,
+        }
+    }
+    pub(crate) fn set_format(&mut self, format: TextureFormat) -> Result<()> {
+        self.format = Some(format);
+        self.pipeline(format, None)?;
+        Ok(())
+    }
+    fn pipeline(
+        &mut self,
+        format: TextureFormat,
+        tile_size: Option<u32>,
+    ) -> Result<&ComputePipeline> {
+        if !self.pipelines.contains_key(&(format, tile_size)) {
+            let source = match tile_size {
+                Some(tile_size) => SEPARABLE_CONVOLVE_TILED_SHADER
+                    .replace("TILE_SIZE", &tile_size.to_string())
+                    .into(),
+                None => Cow::Borrowed(SEPARABLE_CONVOLVE_SHADER),
+            };
+            let shader = shader_with_output_format(
+                &self.context,
+                &format!("{}\n{}", BORDER_SHADER, source),
+                format,
+            )?;
+            let pipeline = self.context.create_compute_pipeline(
+                "separable convolve",
+                &shader,
+                "main",
+                &[
+                    &[
+                        uniform_entry(0),
+                        storage_buffer_entry(1, true),
+                        uniform_entry(2),
+                    ],
+                    &[texture_entry(0), storage_texture_entry(1, format)],
+                ],
+            )?;
+            self.pipelines.insert((format, tile_size), pipeline);
+        }
+        Ok(&self.pipelines[&(format, tile_size)])
+    }
+    /// Replaces both kernels for the next submitted run. The kernel buffers
+    /// are only reallocated when the kernel lengths change.
+    pub fn set_kernels(&mut self, row_kernel: &[f32], column_kernel: &[f32]) -> Result<()> {
+        check_kernel_size(row_kernel.len())?;
+        check_kernel_size(column_kernel.len())?;
+        self.set_packed_kernels(
+            &Kernel::unnormalized(row_kernel.to_vec()),
+            &Kernel::unnormalized(column_kernel.to_vec()),
+        );
+        Ok(())
+    }
+    pub(crate) fn set_packed_kernels(&mut self, row_kernel: &Kernel, column_kernel: &Kernel) {
+        self.horizontal
+            .set_kernel(&self.context, row_kernel, self.method);
+        self.vertical
+            .set_kernel(&self.context, column_kernel, self.method);
+    }
+    /// Lengths of the row and column kernels.
+    pub fn kernel_size(&self) -> (u32, u32) {
+        (self.horizontal.kernel_size, self.vertical.kernel_size)
+    }
+    pub fn method(&self) -> ConvolutionMethod {
+        self.method
+    }
+    /// Chooses between the direct and tiled shaders. Both produce the same
+    /// output; the default picks the faster one for the kernel size.
+    pub fn set_method(&mut self, method: ConvolutionMethod) {
+        self.method = method;
+        for pass in [&mut self.horizontal, &mut self.vertical] {
+            pass.set_method(method);
+        }
+    }
+    pub fn border_mode(&self) -> BorderMode {
+        self.border_mode
+    }
+    /// Changes how pixels outside the image are read by the next submitted
+    /// run. Defaults to `BorderMode::Reflect101`.
+    pub fn set_border_mode(&mut self, border_mode: BorderMode) {
+        self.border_mode = border_mode;
+        self.context.queue.write_buffer(
+            &self.border,
+            0,
+            bytemuck::cast_slice(&border_mode.uniform_data()),
+        );
+    }
+}
+
+impl Filter for SeparableConvolve {
+    fn context(&self) -> &WgContext {
+        &self.context
+    }
+
+    fn outputs(&self) -> Vec<&WgImageBuffer> {
+        self.images.current().iter().skip(1).collect()
+    }
+
+    fn output_descriptor(&self, inputs: &[ImageDescriptor]) -> Result<ImageDescriptor> {
+        let (width, height) = common_size(inputs, 1)?;
+        Ok(ImageDescriptor {
+            width,
+            height,
+            format: output_format(&self.context, self.format, inputs[0].format),
+        })
+    }
+
+    fn encode(&mut self, encoder: &mut CommandEncoder, inputs: &[&WgImageBuffer]) -> Result<()> {
+        let descriptor = self.output_descriptor(&descriptors(inputs)?)?;
+        let input_image = inputs[0];
+        let format = descriptor.format;
+        self.pipeline(format, self.vertical.tile_size)?;
+        self.pipeline(format, self.horizontal.tile_size)?;
+        let vertical_pipeline = &self.pipelines[&(format, self.vertical.tile_size)];
+        let horizontal_pipeline = &self.pipelines[&(format, self.horizontal.tile_size)];
+        let images = self.images.get(&self.context, &[descriptor, descriptor])?;
+        let (vertical_pass_image, output_image) = (&images[0], &images[1]);
+        let vertical_dispatch = self
+            .vertical
+            .dispatch_size(descriptor.width, descriptor.height);
+        let horizontal_dispatch = self
+            .horizontal
+            .dispatch_size(descriptor.width, descriptor.height);
+        let (vertical_constants, vertical_bind_group) = self.vertical.bind_groups(
+            &self.context.device,
+            vertical_pipeline,
+            format,
+            &self.border,
+            input_image,
+            vertical_pass_image,
+        );
+        let (horizontal_constants, horizontal_bind_group) = self.horizontal.bind_groups(
+            &self.context.device,
+            horizontal_pipeline,
+            format,
+            &self.border,
+            vertical_pass_image,
+            output_image,
+        );
+        {
+            let mut compute_pass =
+                encoder.begin_compute_pass(&ComputePassDescriptor { label: None });
+            compute_pass.set_pipeline(vertical_pipeline);
+            compute_pass.set_bind_group(0, vertical_constants, &[]);
+            compute_pass.set_bind_group(1, vertical_bind_group, &[]);
+            compute_pass.dispatch_workgroups(vertical_dispatch.0, vertical_dispatch.1, 1);
+            compute_pass.set_pipeline(horizontal_pipeline);
+            compute_pass.set_bind_group(0, horizontal_constants, &[]);
+            compute_pass.set_bind_group(1, horizontal_bind_group, &[]);
+            compute_pass.dispatch_workgroups(horizontal_dispatch.0, horizontal_dispatch.1, 1);
+        }
+        Ok(())
+    }
+}
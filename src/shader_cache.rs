@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use wgpu::{BindGroupLayoutEntry, ComputePipeline, ShaderModule};
+
+/// Hit and miss counters of a `ShaderCache`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShaderCacheStats {
+    pub module_hits: u64,
+    pub module_misses: u64,
+    pub pipeline_hits: u64,
+    pub pipeline_misses: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    source: String,
+    entry_point: String,
+    bind_group_layouts: Vec<Vec<BindGroupLayoutEntry>>,
+}
+
+/// Compiled shader modules and compute pipelines shared by all filters
+/// created from the same context.
+///
+/// Modules are keyed by their WGSL source and pipelines additionally by entry
+/// point and bind group layouts, so building several filters of the same kind
+/// only compiles their shader once.
+#[derive(Default)]
+pub struct ShaderCache {
+    modules: Mutex<HashMap<String, Arc<ShaderModule>>>,
+    pipelines: Mutex<HashMap<PipelineKey, Arc<ComputePipeline>>>,
+    stats: Mutex<ShaderCacheStats>,
+}
+
+impl ShaderCache {
+    pub(crate) fn module(&self, source: &str) -> Option<Arc<ShaderModule>> {
+        let module = self.modules.lock().unwrap().get(source).cloned();
+        let mut stats = self.stats.lock().unwrap();
+        match module {
+            Some(_) => stats.module_hits += 1,
+            None => stats.module_misses += 1,
+        }
+        module
+    }
+    pub(crate) fn insert_module(&self, source: &str, module: Arc<ShaderModule>) {
+        self.modules
+            .lock()
+            .unwrap()
+            .insert(source.to_owned(), module);
+    }
+    pub(crate) fn pipeline(
+        &self,
+        source: &str,
+        entry_point: &str,
+        bind_group_layouts: &[&[BindGroupLayoutEntry]],
+    ) -> Option<Arc<ComputePipeline>> {
+        let key = pipeline_key(source, entry_point, bind_group_layouts);
+        let pipeline = self.pipelines.lock().unwrap().get(&key).cloned();
+        let mut stats = self.stats.lock().unwrap();
+        match pipeline {
+            Some(_) => stats.pipeline_hits += 1,
+            None => stats.pipeline_misses += 1,
+        }
+        pipeline
+    }
+    pub(crate) fn insert_pipeline(
+        &self,
+        source: &str,
+        entry_point: &str,
+        bind_group_layouts: &[&[BindGroupLayoutEntry]],
+        pipeline: Arc<ComputePipeline>,
+    ) {
+        let key = pipeline_key(source, entry_point, bind_group_layouts);
+        self.pipelines.lock().unwrap().insert(key, pipeline);
+    }
+    pub fn stats(&self) -> ShaderCacheStats {
+        *self.stats.lock().unwrap()
+    }
+    /// Number of cached compute pipelines.
+    pub fn len(&self) -> usize {
+        self.pipelines.lock().unwrap().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Drops all cached modules and pipelines. Filters keep the pipelines
+    /// they already hold.
+    pub fn clear(&self) {
+        self.modules.lock().unwrap().clear();
+        self.pipelines.lock().unwrap().clear();
+    }
+}
+
+fn pipeline_key(
+    source: &str,
+    entry_point: &str,
+    bind_group_layouts: &[&[BindGroupLayoutEntry]],
+) -> PipelineKey {
+    PipelineKey {
+        source: source.to_owned(),
+        entry_point: entry_point.to_owned(),
+        bind_group_layouts: bind_group_layouts
+            .iter()
+            .map(|entries| entries.to_vec())
+            .collect(),
+    }
+}
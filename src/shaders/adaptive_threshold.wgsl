@@ -0,0 +1,39 @@
+struct Settings {
+    max_value : f32,
+    mode : u32,
+    luminance : u32,
+    // C, on a 0 to 1 scale.
+    constant : f32,
+};
+
+@group(0) @binding(0) var<uniform> settings : Settings;
+@group(1) @binding(0) var input_texture : texture_2d<f32>;
+@group(1) @binding(1) var mean_texture : texture_2d<f32>;
+@group(1) @binding(2) var output_texture : texture_storage_2d<OUTPUT_FORMAT, write>;
+
+fn threshold_value(value : f32, mean : f32) -> f32 {
+    return apply_threshold(value, mean - settings.constant, settings.mode, settings.max_value);
+}
+
+@compute
+@workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_invocation_id : vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(global_invocation_id.xy);
+    if(coords.x >= i32(dimensions.x) || coords.y >= i32(dimensions.y)) {
+        return;
+    }
+
+    let color = textureLoad(input_texture, coords.xy, 0);
+    let mean = textureLoad(mean_texture, coords.xy, 0);
+    if (settings.luminance > 0u) {
+        let mask = threshold_value(dot(LUMA_WEIGHTS, color), dot(LUMA_WEIGHTS, mean));
+        textureStore(output_texture, coords.xy, vec4<f32>(mask, mask, mask, color.a));
+        return;
+    }
+    let threshold_r = threshold_value(color.r, mean.r);
+    let threshold_g = threshold_value(color.g, mean.g);
+    let threshold_b = threshold_value(color.b, mean.b);
+
+    textureStore(output_texture, coords.xy, vec4<f32>(threshold_r, threshold_g, threshold_b, color.a));
+}
@@ -0,0 +1,50 @@
+struct Border {
+    mode : u32,
+    constant : vec4<f32>,
+};
+
+// Remainder of `index / period` in [0, period). Only non-negative operands are
+// passed to `%`, whose sign handling differs between backends.
+fn positive_modulo(index : i32, period : i32) -> i32 {
+    if (index >= 0) {
+        return index % period;
+    }
+    return period - 1 - (-index - 1) % period;
+}
+
+// Maps a coordinate along an axis of `size` texels inside the image, or
+// returns -1 when the constant border color should be used instead.
+fn border_index(index : i32, size : i32, mode : u32) -> i32 {
+    if (index >= 0 && index < size) {
+        return index;
+    }
+    switch (mode) {
+        // Replicate: aaa|abcd|ddd
+        case 1u: {
+            return clamp(index, 0, size - 1);
+        }
+        // Reflect: cba|abcd|dcb
+        case 2u: {
+            let period = 2 * size;
+            let i = positive_modulo(index, period);
+            return select(i, period - 1 - i, i >= size);
+        }
+        // Reflect101: dcb|abcd|cba
+        case 3u: {
+            if (size == 1) {
+                return 0;
+            }
+            let period = 2 * size - 2;
+            let i = positive_modulo(index, period);
+            return select(i, period - i, i >= size);
+        }
+        // Wrap: bcd|abcd|abc
+        case 4u: {
+            return positive_modulo(index, size);
+        }
+        // Constant
+        default: {
+            return -1;
+        }
+    }
+}
@@ -0,0 +1,174 @@
+struct Settings {
+    low_threshold : f32,
+    high_threshold : f32,
+    l2_gradient : u32,
+};
+
+struct Control {
+    changed : atomic<u32>,
+    width : u32,
+    height : u32,
+};
+
+@group(0) @binding(0) var<uniform> settings : Settings;
+// Edge class of every pixel: NONE, WEAK or STRONG.
+@group(0) @binding(1) var<storage, read_write> state : array<atomic<u32>>;
+@group(0) @binding(2) var<storage, read_write> control : Control;
+@group(1) @binding(0) var gradient_texture : texture_2d<f32>;
+@group(1) @binding(1) var output_texture : texture_storage_2d<OUTPUT_FORMAT, write>;
+@group(2) @binding(0) var<storage, read_write> dispatch_size : array<u32, 3>;
+
+const NONE : u32 = 0u;
+const WEAK : u32 = 1u;
+const STRONG : u32 = 2u;
+
+// Thresholds are expressed for 8-bit images, like OpenCV.
+fn gradient_at(position : vec2<i32>, dimensions : vec2<i32>) -> vec2<f32> {
+    if (position.x < 0 || position.y < 0 || position.x >= dimensions.x || position.y >= dimensions.y) {
+        return vec2<f32>(0.0, 0.0);
+    }
+    return textureLoad(gradient_texture, position, 0).xy * 255.0;
+}
+
+fn magnitude(gradient : vec2<f32>) -> f32 {
+    if (settings.l2_gradient > 0u) {
+        return length(gradient);
+    }
+    return abs(gradient.x) + abs(gradient.y);
+}
+
+// Non-maximum suppression along the quantized gradient direction followed by
+// the double threshold.
+@compute
+@workgroup_size(16, 16)
+fn suppress(@builtin(global_invocation_id) global_id : vec3<u32>) {
+    let dimensions = vec2<i32>(textureDimensions(gradient_texture));
+    let position = vec2<i32>(global_id.xy);
+    if (all(global_id.xy == vec2<u32>(0u, 0u))) {
+        atomicStore(&control.changed, 1u);
+        control.width = u32(dimensions.x);
+        control.height = u32(dimensions.y);
+    }
+    if (position.x >= dimensions.x || position.y >= dimensions.y) {
+        return;
+    }
+
+    let gradient = gradient_at(position, dimensions);
+    let center = magnitude(gradient);
+    let ax = abs(gradient.x);
+    let ay = abs(gradient.y);
+    // tan(22.5°) and tan(67.5°)
+    var offset = vec2<i32>(1, 0);
+    if (ay > ax * 0.41421356) {
+        if (ay >= ax * 2.41421356) {
+            offset = vec2<i32>(0, 1);
+        } else if ((gradient.x > 0.0) == (gradient.y > 0.0)) {
+            offset = vec2<i32>(1, 1);
+        } else {
+            offset = vec2<i32>(-1, 1);
+        }
+    }
+    let before = magnitude(gradient_at(position - offset, dimensions));
+    let after = magnitude(gradient_at(position + offset, dimensions));
+
+    var edge = NONE;
+    if (center > settings.low_threshold && center > before && center >= after) {
+        edge = select(WEAK, STRONG, center > settings.high_threshold);
+    }
+    atomicStore(&state[position.y * dimensions.x + position.x], edge);
+}
+
+// Sizes the next hysteresis dispatch: nothing once an iteration changed no
+// pixel, so the remaining recorded iterations are free.
+@compute
+@workgroup_size(1)
+fn prepare() {
+    if (atomicLoad(&control.changed) > 0u) {
+        dispatch_size[0] = (control.width + 15u) / 16u;
+        dispatch_size[1] = (control.height + 15u) / 16u;
+    } else {
+        dispatch_size[0] = 0u;
+        dispatch_size[1] = 0u;
+    }
+    dispatch_size[2] = 1u;
+    atomicStore(&control.changed, 0u);
+}
+
+// Tile of 16x16 pixels plus a one pixel border.
+var<workgroup> tile : array<atomic<u32>, 324>;
+// Number of the last pass that promoted an edge of the tile, plus one.
+var<workgroup> last_change : atomic<u32>;
+
+// Propagation passes through a tile per dispatch. The count is fixed so that
+// barriers stay in uniform control flow; edges longer than that are finished
+// by the next dispatch.
+const TILE_PASSES : u32 = 32u;
+
+fn tile_index(local : vec2<i32>) -> i32 {
+    return (local.y + 1) * 18 + local.x + 1;
+}
+
+// Promotes weak edges touching strong ones. Each workgroup propagates edges
+// through its tile in workgroup memory before writing them back.
+@compute
+@workgroup_size(16, 16)
+fn hysteresis(
+    @builtin(workgroup_id) workgroup_id : vec3<u32>,
+    @builtin(local_invocation_id) local_id : vec3<u32>,
+    @builtin(local_invocation_index) local_index : u32,
+) {
+    let dimensions = vec2<i32>(i32(control.width), i32(control.height));
+    let origin = vec2<i32>(workgroup_id.xy) * 16 - 1;
+    for (var i = i32(local_index); i < 324; i = i + 256) {
+        let position = origin + vec2<i32>(i % 18, i / 18);
+        var edge = NONE;
+        if (position.x >= 0 && position.y >= 0 && position.x < dimensions.x && position.y < dimensions.y) {
+            edge = atomicLoad(&state[position.y * dimensions.x + position.x]);
+        }
+        atomicStore(&tile[i], edge);
+    }
+    if (local_index == 0u) {
+        atomicStore(&last_change, 0u);
+    }
+    workgroupBarrier();
+
+    let local = vec2<i32>(local_id.xy);
+    let center = tile_index(local);
+    let initial = atomicLoad(&tile[center]);
+    for (var iteration = 0u; iteration < TILE_PASSES; iteration = iteration + 1u) {
+        // Passes after one that changed nothing have nothing to do. Reading a
+        // change made during this pass only keeps the invocation active.
+        if (atomicLoad(&last_change) >= iteration && atomicLoad(&tile[center]) == WEAK) {
+            var strong = false;
+            for (var dy = -1; dy <= 1; dy = dy + 1) {
+                for (var dx = -1; dx <= 1; dx = dx + 1) {
+                    strong = strong || atomicLoad(&tile[tile_index(local + vec2<i32>(dx, dy))]) == STRONG;
+                }
+            }
+            if (strong) {
+                atomicStore(&tile[center], STRONG);
+                atomicMax(&last_change, iteration + 1u);
+            }
+        }
+        workgroupBarrier();
+    }
+
+    let position = local + origin + 1;
+    if (initial == WEAK && atomicLoad(&tile[center]) == STRONG) {
+        atomicStore(&state[position.y * dimensions.x + position.x], STRONG);
+        atomicStore(&control.changed, 1u);
+    }
+}
+
+@compute
+@workgroup_size(16, 16)
+fn finalize(@builtin(global_invocation_id) global_id : vec3<u32>) {
+    let dimensions = vec2<i32>(textureDimensions(gradient_texture));
+    let position = vec2<i32>(global_id.xy);
+    if (position.x >= dimensions.x || position.y >= dimensions.y) {
+        return;
+    }
+    let edge = atomicLoad(&state[position.y * dimensions.x + position.x]) == STRONG;
+    let value = select(0.0, 1.0, edge);
+    textureStore(output_texture, position, vec4<f32>(value, value, value, 1.0));
+}
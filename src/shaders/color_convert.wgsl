@@ -0,0 +1,256 @@
+// Spaces are numbered like `ColorSpace::shader_index`: RGB, linear RGB, HSV,
+// HSL, YCbCr, XYZ, Lab and Luv.
+struct Settings {
+    from_space : u32,
+    to_space : u32,
+    // Kr, Kb and whether the range is limited, of each YCbCr space.
+    from_ycbcr : vec4<f32>,
+    to_ycbcr : vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> settings : Settings;
+@group(1) @binding(0) var input_texture : texture_2d<f32>;
+@group(1) @binding(1) var output_texture : texture_storage_2d<OUTPUT_FORMAT, write>;
+
+// D65 reference white, like OpenCV.
+const WHITE : vec3<f32> = vec3<f32>(0.950456, 1.0, 1.088754);
+// (6 / 29)^3 and (6 / 29)^2
+const EPSILON : f32 = 0.008856452;
+const DELTA2 : f32 = 0.04280618;
+
+fn srgb_to_linear(value : vec3<f32>) -> vec3<f32> {
+    let low = value / 12.92;
+    let high = pow((max(value, vec3<f32>(0.0)) + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, value <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(value : vec3<f32>) -> vec3<f32> {
+    let low = value * 12.92;
+    let high = 1.055 * pow(max(value, vec3<f32>(0.0)), vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, value <= vec3<f32>(0.0031308));
+}
+
+fn positive_modulo(value : f32, divisor : f32) -> f32 {
+    return value - divisor * floor(value / divisor);
+}
+
+// Hue in degrees of a color whose largest and smallest channels are given.
+fn hue(rgb : vec3<f32>, largest : f32, range : f32) -> f32 {
+    if (range <= 0.0) {
+        return 0.0;
+    }
+    var h : f32;
+    if (largest == rgb.r) {
+        h = (rgb.g - rgb.b) / range;
+    } else if (largest == rgb.g) {
+        h = 2.0 + (rgb.b - rgb.r) / range;
+    } else {
+        h = 4.0 + (rgb.r - rgb.g) / range;
+    }
+    return positive_modulo(60.0 * h, 360.0);
+}
+
+fn rgb_to_hsv(rgb : vec3<f32>) -> vec3<f32> {
+    let largest = max(max(rgb.r, rgb.g), rgb.b);
+    let range = largest - min(min(rgb.r, rgb.g), rgb.b);
+    let s = select(0.0, range / largest, largest > 0.0);
+    return vec3<f32>(hue(rgb, largest, range), s, largest);
+}
+
+fn hsv_to_rgb(hsv : vec3<f32>) -> vec3<f32> {
+    let k = positive_modulo3(vec3<f32>(5.0, 3.0, 1.0) + hsv.x / 60.0, 6.0);
+    let f = clamp(min(k, 4.0 - k), vec3<f32>(0.0), vec3<f32>(1.0));
+    return hsv.z - hsv.z * hsv.y * f;
+}
+
+fn rgb_to_hsl(rgb : vec3<f32>) -> vec3<f32> {
+    let largest = max(max(rgb.r, rgb.g), rgb.b);
+    let smallest = min(min(rgb.r, rgb.g), rgb.b);
+    let range = largest - smallest;
+    let l = 0.5 * (largest + smallest);
+    var s = 0.0;
+    if (range > 0.0) {
+        s = range / select(2.0 - largest - smallest, largest + smallest, l < 0.5);
+    }
+    return vec3<f32>(hue(rgb, largest, range), s, l);
+}
+
+fn hsl_to_rgb(hsl : vec3<f32>) -> vec3<f32> {
+    let k = positive_modulo3(vec3<f32>(0.0, 8.0, 4.0) + hsl.x / 30.0, 12.0);
+    let a = hsl.y * min(hsl.z, 1.0 - hsl.z);
+    let f = clamp(min(k - 3.0, 9.0 - k), vec3<f32>(-1.0), vec3<f32>(1.0));
+    return hsl.z - a * f;
+}
+
+fn positive_modulo3(value : vec3<f32>, divisor : f32) -> vec3<f32> {
+    return value - divisor * floor(value / divisor);
+}
+
+// Y, Cb and Cr normalized like 8-bit digital values: chroma is centered on
+// 128 / 255 and the limited range maps Y to [16, 235] and chroma to
+// [16, 240].
+fn rgb_to_ycbcr(rgb : vec3<f32>, ycbcr : vec4<f32>) -> vec3<f32> {
+    let y = ycbcr.x * rgb.r + (1.0 - ycbcr.x - ycbcr.y) * rgb.g + ycbcr.y * rgb.b;
+    let cb = (rgb.b - y) / (2.0 * (1.0 - ycbcr.y));
+    let cr = (rgb.r - y) / (2.0 * (1.0 - ycbcr.x));
+    if (ycbcr.z > 0.0) {
+        return (vec3<f32>(16.0, 128.0, 128.0) + vec3<f32>(219.0 * y, 224.0 * cb, 224.0 * cr)) / 255.0;
+    }
+    return vec3<f32>(y, cb + 128.0 / 255.0, cr + 128.0 / 255.0);
+}
+
+fn ycbcr_to_rgb(value : vec3<f32>, ycbcr : vec4<f32>) -> vec3<f32> {
+    var y : f32;
+    var cb : f32;
+    var cr : f32;
+    if (ycbcr.z > 0.0) {
+        y = (value.x * 255.0 - 16.0) / 219.0;
+        cb = (value.y * 255.0 - 128.0) / 224.0;
+        cr = (value.z * 255.0 - 128.0) / 224.0;
+    } else {
+        y = value.x;
+        cb = value.y - 128.0 / 255.0;
+        cr = value.z - 128.0 / 255.0;
+    }
+    let r = y + 2.0 * (1.0 - ycbcr.x) * cr;
+    let b = y + 2.0 * (1.0 - ycbcr.y) * cb;
+    let g = (y - ycbcr.x * r - ycbcr.y * b) / (1.0 - ycbcr.x - ycbcr.y);
+    return vec3<f32>(r, g, b);
+}
+
+// Linear sRGB primaries to CIE XYZ.
+fn linear_to_xyz(rgb : vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(
+        dot(vec3<f32>(0.412453, 0.357580, 0.180423), rgb),
+        dot(vec3<f32>(0.212671, 0.715160, 0.072169), rgb),
+        dot(vec3<f32>(0.019334, 0.119193, 0.950227), rgb),
+    );
+}
+
+fn xyz_to_linear(xyz : vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(
+        dot(vec3<f32>(3.240479, -1.537150, -0.498535), xyz),
+        dot(vec3<f32>(-0.969256, 1.875991, 0.041556), xyz),
+        dot(vec3<f32>(0.055648, -0.204043, 1.057311), xyz),
+    );
+}
+
+fn lab_f(t : vec3<f32>) -> vec3<f32> {
+    let cube_root = pow(max(t, vec3<f32>(0.0)), vec3<f32>(1.0 / 3.0));
+    return select(t / (3.0 * DELTA2) + 4.0 / 29.0, cube_root, t > vec3<f32>(EPSILON));
+}
+
+fn lab_f_inverse(f : vec3<f32>) -> vec3<f32> {
+    return select(3.0 * DELTA2 * (f - 4.0 / 29.0), f * f * f, f > vec3<f32>(6.0 / 29.0));
+}
+
+// L in [0, 100], a and b roughly in [-128, 127].
+fn xyz_to_lab(xyz : vec3<f32>) -> vec3<f32> {
+    let f = lab_f(xyz / WHITE);
+    return vec3<f32>(116.0 * f.y - 16.0, 500.0 * (f.x - f.y), 200.0 * (f.y - f.z));
+}
+
+fn lab_to_xyz(lab : vec3<f32>) -> vec3<f32> {
+    let fy = (lab.x + 16.0) / 116.0;
+    let f = vec3<f32>(fy + lab.y / 500.0, fy, fy - lab.z / 200.0);
+    return lab_f_inverse(f) * WHITE;
+}
+
+fn chromaticity(xyz : vec3<f32>) -> vec2<f32> {
+    let denominator = xyz.x + 15.0 * xyz.y + 3.0 * xyz.z;
+    if (denominator <= 0.0) {
+        return vec2<f32>(0.0, 0.0);
+    }
+    return vec2<f32>(4.0 * xyz.x, 9.0 * xyz.y) / denominator;
+}
+
+// L in [0, 100], u in about [-134, 220] and v in about [-140, 122].
+fn xyz_to_luv(xyz : vec3<f32>) -> vec3<f32> {
+    let l = 116.0 * lab_f(vec3<f32>(xyz.y)).x - 16.0;
+    let uv = 13.0 * l * (chromaticity(xyz) - chromaticity(WHITE));
+    return vec3<f32>(l, uv);
+}
+
+fn luv_to_xyz(luv : vec3<f32>) -> vec3<f32> {
+    if (luv.x <= 0.0) {
+        return vec3<f32>(0.0);
+    }
+    let uv = luv.yz / (13.0 * luv.x) + chromaticity(WHITE);
+    let y = lab_f_inverse(vec3<f32>((luv.x + 16.0) / 116.0)).x;
+    let x = y * 9.0 * uv.x / (4.0 * uv.y);
+    let z = y * (12.0 - 3.0 * uv.x - 20.0 * uv.y) / (4.0 * uv.y);
+    return vec3<f32>(x, y, z);
+}
+
+fn to_rgb(value : vec3<f32>) -> vec3<f32> {
+    switch settings.from_space {
+        case 1u: {
+            return linear_to_srgb(value);
+        }
+        case 2u: {
+            return hsv_to_rgb(value);
+        }
+        case 3u: {
+            return hsl_to_rgb(value);
+        }
+        case 4u: {
+            return ycbcr_to_rgb(value, settings.from_ycbcr);
+        }
+        case 5u: {
+            return linear_to_srgb(xyz_to_linear(value));
+        }
+        case 6u: {
+            return linear_to_srgb(xyz_to_linear(lab_to_xyz(value)));
+        }
+        case 7u: {
+            return linear_to_srgb(xyz_to_linear(luv_to_xyz(value)));
+        }
+        default: {
+            return value;
+        }
+    }
+}
+
+fn from_rgb(rgb : vec3<f32>) -> vec3<f32> {
+    switch settings.to_space {
+        case 1u: {
+            return srgb_to_linear(rgb);
+        }
+        case 2u: {
+            return rgb_to_hsv(rgb);
+        }
+        case 3u: {
+            return rgb_to_hsl(rgb);
+        }
+        case 4u: {
+            return rgb_to_ycbcr(rgb, settings.to_ycbcr);
+        }
+        case 5u: {
+            return linear_to_xyz(srgb_to_linear(rgb));
+        }
+        case 6u: {
+            return xyz_to_lab(linear_to_xyz(srgb_to_linear(rgb)));
+        }
+        case 7u: {
+            return xyz_to_luv(linear_to_xyz(srgb_to_linear(rgb)));
+        }
+        default: {
+            return rgb;
+        }
+    }
+}
+
+@compute
+@workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) global_invocation_id : vec3<u32>) {
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(global_invocation_id.xy);
+    if(coords.x >= i32(dimensions.x) || coords.y >= i32(dimensions.y)) {
+        return;
+    }
+
+    let color = textureLoad(input_texture, coords.xy, 0);
+    let converted = from_rgb(to_rgb(color.rgb));
+
+    textureStore(output_texture, coords.xy, vec4<f32>(converted, color.a));
+}
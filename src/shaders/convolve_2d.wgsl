@@ -0,0 +1,46 @@
+struct Settings {
+    kernel_width : u32,
+    kernel_height : u32,
+};
+
+struct Kernel {
+  sum: f32,
+  values : array<f32>,
+};
+
+@group(0) @binding(0) var<uniform> settings : Settings;
+@group(0) @binding(1) var<storage, read> kernel : Kernel;
+@group(0) @binding(2) var<uniform> border : Border;
+@group(1) @binding(0) var input_texture : texture_2d<f32>;
+@group(1) @binding(1) var output_texture : texture_storage_2d<OUTPUT_FORMAT, write>;
+
+@compute
+@workgroup_size(16, 16)
+fn main(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+) {
+    let dimensions = vec2<i32>(textureDimensions(input_texture));
+    let position = vec2<i32>(global_id.xy);
+    if(position.x >= dimensions.x || position.y >= dimensions.y) {
+        return;
+    }
+    let kernel_width = i32(settings.kernel_width);
+    let kernel_height = i32(settings.kernel_height);
+    let radius = vec2<i32>((kernel_width - 1) / 2, (kernel_height - 1) / 2);
+
+    var color : vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    for (var j : i32 = 0; j < kernel_height; j = j + 1) {
+        let y = border_index(position.y - radius.y + j, dimensions.y, border.mode);
+        for (var i : i32 = 0; i < kernel_width; i = i + 1) {
+            let x = border_index(position.x - radius.x + i, dimensions.x, border.mode);
+            var texel = border.constant;
+            if (x >= 0 && y >= 0) {
+                texel = textureLoad(input_texture, vec2<i32>(x, y), 0);
+            }
+            color = color + kernel.values[j * kernel_width + i] * texel;
+        }
+    }
+    color = color / kernel.sum;
+
+    textureStore(output_texture, position, color);
+}
@@ -0,0 +1,79 @@
+struct Settings {
+    kernel_size : u32,
+    // 0: raw derivatives, 1: scaled to [-1, 1], 2: scaled and mapped to [0, 1].
+    normalization : u32,
+    write_magnitude : u32,
+    write_angle : u32,
+    scale : f32,
+};
+
+// Horizontal kernel followed by the vertical one.
+struct Kernel {
+  sum: f32,
+  values : array<f32>,
+};
+
+@group(0) @binding(0) var<uniform> settings : Settings;
+@group(0) @binding(1) var<storage, read> kernel : Kernel;
+@group(0) @binding(2) var<uniform> border : Border;
+@group(1) @binding(0) var input_texture : texture_2d<f32>;
+@group(1) @binding(1) var gradient_texture : texture_storage_2d<OUTPUT_FORMAT, write>;
+@group(1) @binding(2) var magnitude_texture : texture_storage_2d<SCALAR_FORMAT, write>;
+@group(1) @binding(3) var angle_texture : texture_storage_2d<SCALAR_FORMAT, write>;
+
+const PI : f32 = 3.14159265358979;
+
+@compute
+@workgroup_size(16, 16)
+fn main(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+) {
+    let dimensions = vec2<i32>(textureDimensions(input_texture));
+    let position = vec2<i32>(global_id.xy);
+    if(position.x >= dimensions.x || position.y >= dimensions.y) {
+        return;
+    }
+    let kernel_size = i32(settings.kernel_size);
+    let radius = (kernel_size - 1) / 2;
+    let taps = kernel_size * kernel_size;
+
+    var gradient = vec2<f32>(0.0, 0.0);
+    for (var j : i32 = 0; j < kernel_size; j = j + 1) {
+        let y = border_index(position.y - radius + j, dimensions.y, border.mode);
+        for (var i : i32 = 0; i < kernel_size; i = i + 1) {
+            let x = border_index(position.x - radius + i, dimensions.x, border.mode);
+            var texel = border.constant;
+            if (x >= 0 && y >= 0) {
+                texel = textureLoad(input_texture, vec2<i32>(x, y), 0);
+            }
+            let luma = dot(LUMA_WEIGHTS, texel);
+            let index = j * kernel_size + i;
+            gradient = gradient + luma * vec2<f32>(kernel.values[index], kernel.values[taps + index]);
+        }
+    }
+    gradient = gradient * settings.scale;
+    let magnitude = length(gradient);
+    var angle = atan2(gradient.y, gradient.x);
+    if (angle < 0.0) {
+        angle = angle + 2.0 * PI;
+    }
+
+    if (settings.normalization == 2u) {
+        let mapped = gradient * 0.5 + 0.5;
+        textureStore(gradient_texture, position, vec4<f32>(mapped, 0.5, 1.0));
+        if (settings.write_magnitude > 0u) {
+            textureStore(magnitude_texture, position, vec4<f32>(vec3<f32>(magnitude), 1.0));
+        }
+        if (settings.write_angle > 0u) {
+            textureStore(angle_texture, position, vec4<f32>(vec3<f32>(angle / (2.0 * PI)), 1.0));
+        }
+    } else {
+        textureStore(gradient_texture, position, vec4<f32>(gradient, 0.0, 1.0));
+        if (settings.write_magnitude > 0u) {
+            textureStore(magnitude_texture, position, vec4<f32>(magnitude, 0.0, 0.0, 1.0));
+        }
+        if (settings.write_angle > 0u) {
+            textureStore(angle_texture, position, vec4<f32>(angle, 0.0, 0.0, 1.0));
+        }
+    }
+}
@@ -1,5 +1,26 @@
-@group(0) @binding(0) var input_texture : texture_2d<f32>;
-@group(0) @binding(1) var output_texture : texture_storage_2d<rgba8unorm, write>;
+struct Settings {
+    weights : vec4<f32>,
+    // 0: weighted sum, 1: lightness.
+    method : u32,
+    linear_light : u32,
+};
+
+@group(0) @binding(0) var<uniform> settings : Settings;
+@group(1) @binding(0) var input_texture : texture_2d<f32>;
+@group(1) @binding(1) var output_texture : texture_storage_2d<OUTPUT_FORMAT, write>;
+
+fn srgb_to_linear(value : vec3<f32>) -> vec3<f32> {
+    let low = value / 12.92;
+    let high = pow((value + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, value <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(value : f32) -> f32 {
+    if (value <= 0.0031308) {
+        return value * 12.92;
+    }
+    return 1.055 * pow(value, 1.0 / 2.4) - 0.055;
+}
 
 @compute
 @workgroup_size(16, 16)
@@ -11,7 +32,22 @@ fn main(@builtin(global_invocation_id) global_invocation_id : vec3<u32>) {
     }
 
     let color = textureLoad(input_texture, coords.xy, 0);
-    let gray = dot(vec3<f32>(0.299, 0.578, 0.114), color.rgb);
+    // Inputs without color channels are gray already.
+    if (!COLOR_CHANNELS) {
+        textureStore(output_texture, coords.xy, vec4<f32>(color.r, color.r, color.r, color.a));
+        return;
+    }
+    var rgb = color.rgb;
+    if (settings.linear_light > 0u) {
+        rgb = srgb_to_linear(max(rgb, vec3<f32>(0.0)));
+    }
+    var gray = dot(settings.weights.rgb, rgb);
+    if (settings.method == 1u) {
+        gray = 0.5 * (max(max(rgb.r, rgb.g), rgb.b) + min(min(rgb.r, rgb.g), rgb.b));
+    }
+    if (settings.linear_light > 0u) {
+        gray = linear_to_srgb(gray);
+    }
 
     textureStore(output_texture, coords.xy, vec4<f32>(gray, gray, gray, color.a));
 }
@@ -1,8 +1,5 @@
 struct Settings {
     filter_size : u32,
-};
-
-struct Orientation {
     vertical : u32,
 };
 
@@ -13,9 +10,9 @@ struct Kernel {
 
 @group(0) @binding(0) var<uniform> settings : Settings;
 @group(0) @binding(1) var<storage, read> kernel : Kernel;
+@group(0) @binding(2) var<uniform> border : Border;
 @group(1) @binding(0) var input_texture : texture_2d<f32>;
-@group(1) @binding(1) var output_texture : texture_storage_2d<rgba8unorm, write>;
-@group(1) @binding(2) var<uniform> orientation: Orientation;
+@group(1) @binding(1) var output_texture : texture_storage_2d<OUTPUT_FORMAT, write>;
 
 @compute
 @workgroup_size(128)
@@ -26,7 +23,7 @@ fn main(
     let filter_size = i32(settings.filter_size);
     let dimensions = textureDimensions(input_texture);
     var position = vec2<i32>(global_id.xy);
-    if (orientation.vertical == 0u) {
+    if (settings.vertical == 0u) {
         position = position.yx;
     }
     
@@ -34,17 +31,22 @@ fn main(
         return;
     }
 
-    let original = textureLoad(input_texture, position, 0);
     var color : vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 0.0);
     
     for (var i : i32 = 0; i < filter_size; i = i + 1) {
-        if (orientation.vertical > 0u) {
-            let y = position.y - filter_radius + i;
-            color = color + kernel.values[i] * textureLoad(input_texture, vec2<i32>(position.x, y), 0);
+        var texel = border.constant;
+        if (settings.vertical > 0u) {
+            let y = border_index(position.y - filter_radius + i, i32(dimensions.y), border.mode);
+            if (y >= 0) {
+                texel = textureLoad(input_texture, vec2<i32>(position.x, y), 0);
+            }
         } else {
-            let x = position.x - filter_radius + i;
-            color = color + kernel.values[i] * textureLoad(input_texture, vec2<i32>(x, position.y), 0);
+            let x = border_index(position.x - filter_radius + i, i32(dimensions.x), border.mode);
+            if (x >= 0) {
+                texel = textureLoad(input_texture, vec2<i32>(x, position.y), 0);
+            }
         }
+        color = color + kernel.values[i] * texel;
     }
     color = color / kernel.sum;
 
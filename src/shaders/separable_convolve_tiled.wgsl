@@ -0,0 +1,73 @@
+struct Settings {
+    filter_size : u32,
+    vertical : u32,
+};
+
+struct Kernel {
+  sum: f32,
+  values : array<f32>,
+};
+
+@group(0) @binding(0) var<uniform> settings : Settings;
+@group(0) @binding(1) var<storage, read> kernel : Kernel;
+@group(0) @binding(2) var<uniform> border : Border;
+@group(1) @binding(0) var input_texture : texture_2d<f32>;
+@group(1) @binding(1) var output_texture : texture_storage_2d<OUTPUT_FORMAT, write>;
+
+// 128 outputs plus the halo of the kernel on each side. TILE_SIZE is chosen
+// per kernel size since large tiles are slow to zero-initialize.
+var<workgroup> tile : array<vec4<f32>, TILE_SIZE>;
+
+fn load(axis_index : i32, line : i32, dimensions : vec2<i32>) -> vec4<f32> {
+    if (settings.vertical > 0u) {
+        let y = border_index(axis_index, dimensions.y, border.mode);
+        if (y >= 0 && line < dimensions.x) {
+            return textureLoad(input_texture, vec2<i32>(line, y), 0);
+        }
+    } else {
+        let x = border_index(axis_index, dimensions.x, border.mode);
+        if (x >= 0 && line < dimensions.y) {
+            return textureLoad(input_texture, vec2<i32>(x, line), 0);
+        }
+    }
+    return border.constant;
+}
+
+// Each workgroup convolves 128 consecutive texels of one row or column: the
+// texels they read are loaded into `tile` once, then every invocation
+// convolves from workgroup memory.
+@compute
+@workgroup_size(128)
+fn main(
+  @builtin(global_invocation_id) global_id : vec3<u32>,
+  @builtin(local_invocation_id) local_id : vec3<u32>,
+  @builtin(workgroup_id) workgroup_id : vec3<u32>,
+) {
+    let filter_radius = i32((settings.filter_size - 1u) / 2u);
+    let filter_size = i32(settings.filter_size);
+    let dimensions = vec2<i32>(textureDimensions(input_texture));
+    let line = i32(global_id.y);
+    let tile_start = i32(workgroup_id.x) * 128 - filter_radius;
+    let tile_size = 128 + 2 * filter_radius;
+
+    for (var i : i32 = i32(local_id.x); i < tile_size; i = i + 128) {
+        tile[i] = load(tile_start + i, line, dimensions);
+    }
+    workgroupBarrier();
+
+    var position = vec2<i32>(line, i32(global_id.x));
+    if (settings.vertical == 0u) {
+        position = position.yx;
+    }
+    if(position.x >= dimensions.x || position.y >= dimensions.y) {
+        return;
+    }
+
+    var color : vec4<f32> = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    for (var i : i32 = 0; i < filter_size; i = i + 1) {
+        color = color + kernel.values[i] * tile[i32(local_id.x) + i];
+    }
+    color = color / kernel.sum;
+
+    textureStore(output_texture, position, color);
+}
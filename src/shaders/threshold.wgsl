@@ -1,10 +1,19 @@
 struct Settings {
-    threshold : f32,
+    max_value : f32,
+    mode : u32,
+    luminance : u32,
 };
 
 @group(0) @binding(0) var<uniform> settings : Settings;
+// Fixed or selected threshold, on a 0 to 255 scale.
+@group(0) @binding(1) var<storage, read> threshold_level : u32;
 @group(1) @binding(0) var input_texture : texture_2d<f32>;
-@group(1) @binding(1) var output_texture : texture_storage_2d<rgba8unorm, write>;
+@group(1) @binding(1) var output_texture : texture_storage_2d<OUTPUT_FORMAT, write>;
+
+fn threshold_value(value : f32) -> f32 {
+    let threshold = f32(threshold_level) / 255.0;
+    return apply_threshold(value, threshold, settings.mode, settings.max_value);
+}
 
 @compute
 @workgroup_size(16, 16)
@@ -16,9 +25,14 @@ fn main(@builtin(global_invocation_id) global_invocation_id : vec3<u32>) {
     }
 
     let color = textureLoad(input_texture, coords.xy, 0);
-    let threshold_r = select(1.0, 0.0, color.r > settings.threshold);
-    let threshold_g = select(1.0, 0.0, color.g > settings.threshold);
-    let threshold_b = select(1.0, 0.0, color.b > settings.threshold);
+    if (settings.luminance > 0u) {
+        let mask = threshold_value(dot(LUMA_WEIGHTS, color));
+        textureStore(output_texture, coords.xy, vec4<f32>(mask, mask, mask, color.a));
+        return;
+    }
+    let threshold_r = threshold_value(color.r);
+    let threshold_g = threshold_value(color.g);
+    let threshold_b = threshold_value(color.b);
 
     textureStore(output_texture, coords.xy, vec4<f32>(threshold_r, threshold_g, threshold_b, color.a));
 }
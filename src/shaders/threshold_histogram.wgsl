@@ -0,0 +1,127 @@
+@group(0) @binding(0) var<storage, read_write> histogram : array<atomic<u32>, 256>;
+// Selected threshold, on a 0 to 255 scale.
+@group(0) @binding(1) var<storage, read_write> threshold_level : u32;
+@group(1) @binding(0) var input_texture : texture_2d<f32>;
+
+var<workgroup> local_histogram : array<atomic<u32>, 256>;
+
+// Histogram of the luminance on 256 levels, accumulated per workgroup first.
+@compute
+@workgroup_size(16, 16)
+fn histogram_main(
+    @builtin(global_invocation_id) global_invocation_id : vec3<u32>,
+    @builtin(local_invocation_index) local_index : u32,
+) {
+    atomicStore(&local_histogram[local_index], 0u);
+    workgroupBarrier();
+
+    let dimensions = textureDimensions(input_texture);
+    let coords = vec2<i32>(global_invocation_id.xy);
+    if (coords.x < i32(dimensions.x) && coords.y < i32(dimensions.y)) {
+        let luma = dot(LUMA_WEIGHTS, textureLoad(input_texture, coords, 0));
+        // Rounded up, so that exactly the levels above the selected threshold
+        // are above it in `apply_threshold`, whose tolerance is matched here.
+        let level = u32(ceil(clamp(luma, 0.0, 1.0) * 255.0 - 255.0 * 1e-5));
+        atomicAdd(&local_histogram[level], 1u);
+    }
+    workgroupBarrier();
+
+    let count = atomicLoad(&local_histogram[local_index]);
+    if (count > 0u) {
+        atomicAdd(&histogram[local_index], count);
+    }
+}
+
+// Maximizes the between-class variance, like OpenCV's THRESH_OTSU.
+@compute
+@workgroup_size(1)
+fn otsu_main() {
+    var total = 0.0;
+    var mean = 0.0;
+    for (var i = 0u; i < 256u; i = i + 1u) {
+        let count = f32(atomicLoad(&histogram[i]));
+        total = total + count;
+        mean = mean + f32(i) * count;
+    }
+    mean = mean / max(total, 1.0);
+
+    var q1 = 0.0;
+    var mean1 = 0.0;
+    var max_sigma = 0.0;
+    var level = 0u;
+    for (var i = 0u; i < 256u; i = i + 1u) {
+        let p = f32(atomicLoad(&histogram[i])) / max(total, 1.0);
+        mean1 = mean1 * q1;
+        q1 = q1 + p;
+        let q2 = 1.0 - q1;
+        if (min(q1, q2) < 1e-6 || max(q1, q2) > 1.0 - 1e-6) {
+            continue;
+        }
+        mean1 = (mean1 + f32(i) * p) / q1;
+        let mean2 = (mean - q1 * mean1) / q2;
+        let sigma = q1 * q2 * (mean1 - mean2) * (mean1 - mean2);
+        if (sigma > max_sigma) {
+            max_sigma = sigma;
+            level = i;
+        }
+    }
+    threshold_level = level;
+}
+
+fn bin(index : i32, flipped : bool) -> f32 {
+    return f32(atomicLoad(&histogram[select(index, 255 - index, flipped)]));
+}
+
+// Finds the level farthest from the line joining the histogram peak to the
+// end of its longer tail, like OpenCV's THRESH_TRIANGLE.
+@compute
+@workgroup_size(1)
+fn triangle_main() {
+    var left_bound = 0;
+    var right_bound = 255;
+    var max_index = 0;
+    var max_count = 0u;
+    for (var i = 0; i < 256; i = i + 1) {
+        if (atomicLoad(&histogram[i]) > 0u) {
+            left_bound = i;
+            break;
+        }
+    }
+    for (var i = 255; i >= 0; i = i - 1) {
+        if (atomicLoad(&histogram[i]) > 0u) {
+            right_bound = i;
+            break;
+        }
+    }
+    left_bound = max(left_bound - 1, 0);
+    right_bound = min(right_bound + 1, 255);
+    for (var i = 0; i < 256; i = i + 1) {
+        let count = atomicLoad(&histogram[i]);
+        if (count > max_count) {
+            max_count = count;
+            max_index = i;
+        }
+    }
+
+    let flipped = max_index - left_bound < right_bound - max_index;
+    if (flipped) {
+        left_bound = 255 - right_bound;
+        max_index = 255 - max_index;
+    }
+    var level = left_bound;
+    let a = f32(max_count);
+    let b = f32(left_bound - max_index);
+    var max_distance = 0.0;
+    for (var i = left_bound + 1; i <= max_index; i = i + 1) {
+        let distance = a * f32(i) + b * bin(i, flipped);
+        if (distance > max_distance) {
+            max_distance = distance;
+            level = i;
+        }
+    }
+    level = level - 1;
+    if (flipped) {
+        level = 255 - level;
+    }
+    threshold_level = u32(clamp(level, 0, 255));
+}
@@ -0,0 +1,28 @@
+// Maps a value to the output of a `ThresholdMode`.
+fn apply_threshold(value : f32, threshold : f32, mode : u32, max_value : f32) -> f32 {
+    // Tolerates the rounding of normalized reads, so that 8-bit values equal
+    // to the threshold are not above it.
+    let above = value - threshold > 1e-5;
+    switch mode {
+        // Binary
+        case 0u: {
+            return select(0.0, max_value, above);
+        }
+        // BinaryInv
+        case 1u: {
+            return select(max_value, 0.0, above);
+        }
+        // Trunc
+        case 2u: {
+            return select(value, threshold, above);
+        }
+        // ToZero
+        case 3u: {
+            return select(0.0, value, above);
+        }
+        // ToZeroInv
+        default: {
+            return select(value, 0.0, above);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use wgpu::{Buffer, BufferDescriptor, BufferUsages, Device};
+
+/// Idle buffers kept per size class; extra released buffers are dropped.
+const MAX_IDLE_BUFFERS_PER_CLASS: usize = 4;
+/// Granularity of the size classes, which bounds the unused tail of a
+/// buffer to less than a page.
+const SIZE_CLASS_GRANULARITY: u64 = 4096;
+
+/// Recycles the `MAP_READ` buffers used for readbacks.
+///
+/// Buffers are bucketed by size class, the requested size rounded up to a
+/// multiple of 4 KiB, so frames of the same resolution reuse the same
+/// buffers.
+#[derive(Default)]
+pub struct StagingPool {
+    buffers: Mutex<HashMap<u64, Vec<Buffer>>>,
+}
+
+impl StagingPool {
+    /// Returns an unmapped buffer of at least `size` bytes.
+    pub(crate) fn acquire(&self, device: &Device, size: u64) -> Buffer {
+        let size_class = size.max(1).next_multiple_of(SIZE_CLASS_GRANULARITY);
+        let recycled = self
+            .buffers
+            .lock()
+            .unwrap()
+            .get_mut(&size_class)
+            .and_then(Vec::pop);
+        recycled.unwrap_or_else(|| {
+            device.create_buffer(&BufferDescriptor {
+                label: Some("staging buffer"),
+                size: size_class,
+                usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        })
+    }
+    /// Returns an unmapped buffer obtained from `acquire` to the pool.
+    pub(crate) fn release(&self, buffer: Buffer) {
+        let mut buffers = self.buffers.lock().unwrap();
+        let idle = buffers.entry(buffer.size()).or_default();
+        if idle.len() < MAX_IDLE_BUFFERS_PER_CLASS {
+            idle.push(buffer);
+        }
+    }
+    /// Number of idle buffers currently held by the pool.
+    pub fn len(&self) -> usize {
+        self.buffers.lock().unwrap().values().map(Vec::len).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Drops all idle buffers.
+    pub fn clear(&self) {
+        self.buffers.lock().unwrap().clear();
+    }
+}
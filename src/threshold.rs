@@ -1,89 +1,487 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::channel::oneshot;
+use futures::executor::block_on;
 use wgpu::util::DeviceExt;
 use wgpu::{
-    util::BufferInitDescriptor, BindGroupDescriptor, BindGroupEntry, BindingResource, Buffer,
-    BufferUsages, ComputePipeline, ComputePipelineDescriptor, ShaderModuleDescriptor, ShaderSource,
-    TextureViewDescriptor,
+    util::BufferInitDescriptor, BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource,
+    Buffer, BufferAsyncError, BufferUsages, CommandEncoder, CommandEncoderDescriptor,
+    ComputePipeline, TextureFormat,
 };
 
 use super::buffer::WgImageBuffer;
 use super::context::WgContext;
-use super::utils::compute_work_group_count;
+use super::error::Result;
+use super::filter::{
+    common_size, descriptors, has_color_channels, luma_weights, single_channel_format,
+    BindGroupCache, Filter, ImageCache, ImageDescriptor,
+};
+use super::utils::{
+    compute_work_group_count, shader_with_output_format, storage_buffer_entry,
+    storage_texture_entry, texture_entry, uniform_entry,
+};
 
 const THRESHOLD_SHADER: &str = include_str!("shaders/threshold.wgsl");
+/// `apply_threshold` function shared by the thresholding shaders.
+pub(crate) const THRESHOLD_MODE_SHADER: &str = include_str!("shaders/threshold_mode.wgsl");
+const HISTOGRAM_SHADER: &str = include_str!("shaders/threshold_histogram.wgsl");
 
-pub struct Threshold<'a> {
-    pub output_image: WgImageBuffer,
-    context: &'a WgContext,
-    pipeline: ComputePipeline,
-    settings: Buffer,
+/// How `Threshold` maps values to outputs, named after OpenCV's threshold
+/// types.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ThresholdMode {
+    /// The max value above the threshold, 0 elsewhere.
+    #[default]
+    Binary,
+    /// 0 above the threshold, the max value elsewhere.
+    BinaryInv,
+    /// The threshold above it, the value elsewhere.
+    Trunc,
+    /// The value above the threshold, 0 elsewhere.
+    ToZero,
+    /// 0 above the threshold, the value elsewhere.
+    ToZeroInv,
 }
 
-impl<'a> Threshold<'a> {
-    pub fn new(context: &'a WgContext, width: u32, height: u32, threshold: u32) -> Self {
-        let threshold: f32 = threshold as f32 / 255.0;
-        let output_image = WgImageBuffer::from_size(context, width, height);
-        let shader = context.device.create_shader_module(ShaderModuleDescriptor {
-            label: Some("threshold shader"),
-            source: ShaderSource::Wgsl(THRESHOLD_SHADER.into()),
+impl ThresholdMode {
+    /// Value of the `mode` argument of `apply_threshold`.
+    pub(crate) fn shader_index(self) -> u32 {
+        match self {
+            ThresholdMode::Binary => 0,
+            ThresholdMode::BinaryInv => 1,
+            ThresholdMode::Trunc => 2,
+            ThresholdMode::ToZero => 3,
+            ThresholdMode::ToZeroInv => 4,
+        }
+    }
+}
+
+/// How `Threshold` chooses its threshold.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ThresholdMethod {
+    /// The value given to `new` or `set_threshold`.
+    #[default]
+    Fixed,
+    /// Otsu's method, which maximizes the variance between the two classes.
+    Otsu,
+    /// The triangle method, suited to histograms with a single dominant peak.
+    Triangle,
+}
+
+impl ThresholdMethod {
+    fn entry_point(self) -> &'static str {
+        match self {
+            ThresholdMethod::Fixed => unreachable!("fixed thresholds are not selected"),
+            ThresholdMethod::Otsu => "otsu_main",
+            ThresholdMethod::Triangle => "triangle_main",
+        }
+    }
+}
+
+/// Histogram and selection passes computing the threshold of the automatic
+/// methods on the GPU.
+struct ThresholdSelection {
+    /// Histogram and selection pipelines by method and whether the input has
+    /// color channels.
+    pipelines: HashMap<(ThresholdMethod, bool), (Arc<ComputePipeline>, Arc<ComputePipeline>)>,
+    histogram: Buffer,
+    constants: Option<BindGroup>,
+    bind_groups: BindGroupCache,
+}
+
+impl ThresholdSelection {
+    fn new(context: &WgContext) -> Self {
+        let histogram = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Histogram"),
+            size: 256 * 4,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        ThresholdSelection {
+            pipelines: HashMap::new(),
+            histogram,
+            constants: None,
+            bind_groups: BindGroupCache::default(),
+        }
+    }
+    fn pipelines(
+        &mut self,
+        context: &WgContext,
+        method: ThresholdMethod,
+        color: bool,
+    ) -> Result<&(Arc<ComputePipeline>, Arc<ComputePipeline>)> {
+        let key = (method, color);
+        if let Entry::Vacant(entry) = self.pipelines.entry(key) {
+            let shader = HISTOGRAM_SHADER.replace("LUMA_WEIGHTS", luma_weights(color));
+            let layouts: [&[_]; 2] = [
+                &[
+                    storage_buffer_entry(0, false),
+                    storage_buffer_entry(1, false),
+                ],
+                &[texture_entry(0)],
+            ];
+            let histogram = context.create_compute_pipeline(
+                "histogram",
+                &shader,
+                "histogram_main",
+                &layouts,
+            )?;
+            let selection = context.create_compute_pipeline(
+                "threshold selection",
+                &shader,
+                method.entry_point(),
+                &layouts,
+            )?;
+            entry.insert((histogram, selection));
+        }
+        Ok(&self.pipelines[&key])
+    }
+    /// Records the passes writing the threshold of `input_image` into
+    /// `level`.
+    fn encode(
+        &mut self,
+        context: &WgContext,
+        encoder: &mut CommandEncoder,
+        method: ThresholdMethod,
+        input_image: &WgImageBuffer,
+        level: &Buffer,
+    ) -> Result<()> {
+        let color = has_color_channels(input_image.format());
+        self.pipelines(context, method, color)?;
+        let (histogram_pipeline, selection_pipeline) = &self.pipelines[&(method, color)];
+        let device = &context.device;
+        let histogram = &self.histogram;
+        let constants = self.constants.get_or_insert_with(|| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Histogram constants"),
+                layout: &histogram_pipeline.get_bind_group_layout(0),
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: histogram.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: level.as_entire_binding(),
+                    },
+                ],
+            })
         });
+        let image_bind_group = self.bind_groups.get_or_insert_with(&[input_image], || {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Texture bind group"),
+                layout: &histogram_pipeline.get_bind_group_layout(1),
+                entries: &[BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(input_image.view()),
+                }],
+            })
+        });
+        encoder.clear_buffer(histogram, 0, None);
+        {
+            let (dispatch_width, dispatch_height) = compute_work_group_count(
+                (
+                    input_image.texture_extent.width,
+                    input_image.texture_extent.height,
+                ),
+                (16, 16),
+            );
+            let mut compute_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            compute_pass.set_pipeline(histogram_pipeline);
+            compute_pass.set_bind_group(0, constants, &[]);
+            compute_pass.set_bind_group(1, image_bind_group, &[]);
+            compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
+            compute_pass.set_pipeline(selection_pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+        Ok(())
+    }
+}
+
+type ThresholdReceiver = oneshot::Receiver<std::result::Result<(), BufferAsyncError>>;
+
+/// Thresholds every color channel independently, or the luminance of the
+/// input when `set_luminance_mask` is enabled.
+pub struct Threshold {
+    context: WgContext,
+    format: Option<TextureFormat>,
+    /// Pipelines by output format and whether the input has color channels.
+    pipelines: HashMap<(TextureFormat, bool), Arc<ComputePipeline>>,
+    images: ImageCache,
+    /// Settings bind group of each pipeline.
+    constants: HashMap<(TextureFormat, bool), BindGroup>,
+    bind_groups: BindGroupCache,
+    settings: Buffer,
+    /// Threshold applied by the shader, written by the host or by `selection`.
+    level: Buffer,
+    selection: ThresholdSelection,
+    method: ThresholdMethod,
+    threshold: u32,
+    max_value: u32,
+    mode: ThresholdMode,
+    luminance_mask: bool,
+}
+
+impl Threshold {
+    /// Writes outputs in the input format when possible, see `with_format`.
+    /// Thresholds are on a 0 to 255 scale, whatever the input format.
+    pub fn new(context: &WgContext, threshold: u32) -> Result<Self> {
         let settings = context.device.create_buffer_init(&BufferInitDescriptor {
             label: Some("Image info"),
+            contents: &[0; 16],
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+        let level = context.device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Threshold level"),
             contents: bytemuck::cast_slice(&[threshold]),
-            usage: BufferUsages::UNIFORM,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
         });
 
-        let pipeline = context
-            .device
-            .create_compute_pipeline(&ComputePipelineDescriptor {
-                label: Some("threshold pipeline"),
-                layout: None,
-                module: &shader,
-                entry_point: "main",
-            });
-
-        Threshold {
-            output_image,
-            context,
-            pipeline,
+        let filter = Threshold {
+            context: context.clone(),
+            format: None,
+            pipelines: HashMap::new(),
+            images: ImageCache::default(),
+            constants: HashMap::new(),
+            bind_groups: BindGroupCache::default(),
             settings,
+            level,
+            selection: ThresholdSelection::new(context),
+            method: ThresholdMethod::Fixed,
+            threshold,
+            max_value: 255,
+            mode: ThresholdMode::default(),
+            luminance_mask: false,
+        };
+        filter.write_settings();
+        Ok(filter)
+    }
+    /// Selects the threshold of every input with Otsu's method, on the GPU.
+    /// The histogram is built from the input's 8-bit luminance, also when
+    /// thresholding channels independently.
+    pub fn otsu(context: &WgContext) -> Result<Self> {
+        let mut filter = Self::new(context, 0)?;
+        filter.method = ThresholdMethod::Otsu;
+        Ok(filter)
+    }
+    /// Selects the threshold of every input with the triangle method, like
+    /// `otsu`.
+    pub fn triangle(context: &WgContext) -> Result<Self> {
+        let mut filter = Self::new(context, 0)?;
+        filter.method = ThresholdMethod::Triangle;
+        Ok(filter)
+    }
+    /// Always writes outputs of `format`.
+    pub fn with_format(context: &WgContext, threshold: u32, format: TextureFormat) -> Result<Self> {
+        let mut filter = Self::new(context, threshold)?;
+        filter.format = Some(format);
+        filter.pipeline(format, true)?;
+        Ok(filter)
+    }
+    fn pipeline(&mut self, format: TextureFormat, color: bool) -> Result<&ComputePipeline> {
+        let key = (format, color);
+        if !self.pipelines.contains_key(&key) {
+            let source = format!("{}\n{}", THRESHOLD_MODE_SHADER, THRESHOLD_SHADER)
+                .replace("LUMA_WEIGHTS", luma_weights(color));
+            let shader = shader_with_output_format(&self.context, &source, format)?;
+            let pipeline = self.context.create_compute_pipeline(
+                "threshold",
+                &shader,
+                "main",
+                &[
+                    &[uniform_entry(0), storage_buffer_entry(1, true)],
+                    &[texture_entry(0), storage_texture_entry(1, format)],
+                ],
+            )?;
+            self.pipelines.insert(key, pipeline);
         }
+        Ok(&self.pipelines[&key])
     }
-    pub fn run(&mut self, input_image: &WgImageBuffer) {
-        let compute_constants = self.context.device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Compute constants"),
-            layout: &self.pipeline.get_bind_group_layout(0),
-            entries: &[BindGroupEntry {
-                binding: 0,
-                resource: self.settings.as_entire_binding(),
-            }],
-        });
-        let image_bind_group = self.context.device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Texture bind group"),
-            layout: &self.pipeline.get_bind_group_layout(1),
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(
-                        &input_image
-                            .texture
-                            .create_view(&TextureViewDescriptor::default()),
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::TextureView(
-                        &self
-                            .output_image
-                            .texture
-                            .create_view(&TextureViewDescriptor::default()),
-                    ),
-                },
-            ],
-        });
+    fn write_settings(&self) {
+        let settings = [
+            f32::to_bits(self.max_value as f32 / 255.0),
+            self.mode.shader_index(),
+            self.luminance_mask as u32,
+            0,
+        ];
+        self.context
+            .queue
+            .write_buffer(&self.settings, 0, bytemuck::cast_slice(&settings));
+    }
+    /// Fixed threshold, see `read_threshold` for the selected ones.
+    pub fn threshold(&self) -> u32 {
+        self.threshold
+    }
+    /// Changes the threshold used by the next submitted run, switching to
+    /// `ThresholdMethod::Fixed`.
+    pub fn set_threshold(&mut self, threshold: u32) {
+        self.threshold = threshold;
+        self.set_method(ThresholdMethod::Fixed);
+    }
+    pub fn method(&self) -> ThresholdMethod {
+        self.method
+    }
+    pub fn set_method(&mut self, method: ThresholdMethod) {
+        self.method = method;
+        if method == ThresholdMethod::Fixed {
+            self.context.queue.write_buffer(
+                &self.level,
+                0,
+                bytemuck::cast_slice(&[self.threshold]),
+            );
+        }
+    }
+    /// Reads back the threshold applied by the last submitted run, on a 0 to
+    /// 255 scale, blocking until the GPU has finished it.
+    pub fn read_threshold(&self) -> Result<u32> {
+        let readback = self.begin_threshold_readback();
+        self.context.device.poll(wgpu::Maintain::Wait);
+        block_on(self.end_threshold_readback(readback))
+    }
+    /// Non-blocking variant of `read_threshold`, which like
+    /// `WgImageBuffer::to_host_image_async` only completes once the device
+    /// has been polled.
+    pub async fn read_threshold_async(&self) -> Result<u32> {
+        let readback = self.begin_threshold_readback();
+        self.end_threshold_readback(readback).await
+    }
+    fn begin_threshold_readback(&self) -> (Buffer, ThresholdReceiver) {
+        let buffer = self.context.staging_pool().acquire(&self.context.device, 4);
         let mut encoder = self
             .context
             .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(&self.level, 0, &buffer, 0, 4);
+        self.context.queue.submit(Some(encoder.finish()));
+        let (sender, receiver) = oneshot::channel();
+        buffer
+            .slice(..4)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        (buffer, receiver)
+    }
+    async fn end_threshold_readback(
+        &self,
+        (buffer, receiver): (Buffer, ThresholdReceiver),
+    ) -> Result<u32> {
+        receiver.await.unwrap_or(Err(BufferAsyncError))?;
+        let level = {
+            let data = buffer.slice(..4).get_mapped_range();
+            u32::from_ne_bytes([data[0], data[1], data[2], data[3]])
+        };
+        buffer.unmap();
+        self.context.staging_pool().release(buffer);
+        Ok(level)
+    }
+    pub fn max_value(&self) -> u32 {
+        self.max_value
+    }
+    /// Changes the value written by the binary modes, 255 by default.
+    pub fn set_max_value(&mut self, max_value: u32) {
+        self.max_value = max_value;
+        self.write_settings();
+    }
+    pub fn mode(&self) -> ThresholdMode {
+        self.mode
+    }
+    pub fn set_mode(&mut self, mode: ThresholdMode) {
+        self.mode = mode;
+        self.write_settings();
+    }
+    pub fn luminance_mask(&self) -> bool {
+        self.luminance_mask
+    }
+    /// Thresholds the BT.601 luminance instead of each channel, writing a
+    /// single mask. Unless a format was requested, the mask is a
+    /// single-channel image: `R8Unorm` on devices that can write it, which
+    /// requires `Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`, and
+    /// `R32Float` with the same 0 to 1 values otherwise. Runs fail with
+    /// `Error::UnsupportedTexture` if neither can be written.
+    pub fn set_luminance_mask(&mut self, enabled: bool) {
+        self.luminance_mask = enabled;
+        self.write_settings();
+    }
+}
+
+impl Filter for Threshold {
+    fn context(&self) -> &WgContext {
+        &self.context
+    }
+
+    fn outputs(&self) -> Vec<&WgImageBuffer> {
+        self.images.current().iter().collect()
+    }
+
+    fn output_descriptor(&self, inputs: &[ImageDescriptor]) -> Result<ImageDescriptor> {
+        let (width, height) = common_size(inputs, 1)?;
+        Ok(ImageDescriptor {
+            width,
+            height,
+            format: single_channel_format(
+                &self.context,
+                self.format,
+                self.luminance_mask,
+                inputs[0].format,
+            )?,
+        })
+    }
+
+    fn encode(&mut self, encoder: &mut CommandEncoder, inputs: &[&WgImageBuffer]) -> Result<()> {
+        let descriptor = self.output_descriptor(&descriptors(inputs)?)?;
+        let input_image = inputs[0];
+        let key = (descriptor.format, has_color_channels(input_image.format()));
+        self.pipeline(key.0, key.1)?;
+        let pipeline = &self.pipelines[&key];
+        let output_image = &self.images.get(&self.context, &[descriptor])?[0];
+        if self.method != ThresholdMethod::Fixed {
+            self.selection.encode(
+                &self.context,
+                encoder,
+                self.method,
+                input_image,
+                &self.level,
+            )?;
+        }
+        let device = &self.context.device;
+        let (settings, level) = (&self.settings, &self.level);
+        let compute_constants = self.constants.entry(key).or_insert_with(|| {
+            device.create_bind_group(&BindGroupDescriptor {
+                label: Some("Compute constants"),
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: settings.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: level.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+        let image_bind_group =
+            self.bind_groups
+                .get_or_insert_with(&[input_image, output_image], || {
+                    device.create_bind_group(&BindGroupDescriptor {
+                        label: Some("Texture bind group"),
+                        layout: &pipeline.get_bind_group_layout(1),
+                        entries: &[
+                            BindGroupEntry {
+                                binding: 0,
+                                resource: BindingResource::TextureView(input_image.view()),
+                            },
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: BindingResource::TextureView(output_image.view()),
+                            },
+                        ],
+                    })
+                });
         {
             let (dispatch_width, dispatch_height) = compute_work_group_count(
                 (
@@ -94,11 +492,11 @@ impl<'a> Threshold<'a> {
             );
             let mut compute_pass =
                 encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
-            compute_pass.set_pipeline(&self.pipeline);
-            compute_pass.set_bind_group(0, &compute_constants, &[]);
-            compute_pass.set_bind_group(1, &image_bind_group, &[]);
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, compute_constants, &[]);
+            compute_pass.set_bind_group(1, image_bind_group, &[]);
             compute_pass.dispatch_workgroups(dispatch_width, dispatch_height, 1);
         }
-        self.context.queue.submit(Some(encoder.finish()));
+        Ok(())
     }
 }
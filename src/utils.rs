@@ -1,14 +1,144 @@
+use wgpu::{
+    BindGroupLayoutEntry, BindingType, BufferBindingType, Extent3d, ShaderStages,
+    StorageTextureAccess, TextureFormat, TextureSampleType, TextureViewDimension,
+};
+
+use super::context::WgContext;
+use super::error::{Error, Result};
+
 pub fn compute_work_group_count(
     (width, height): (u32, u32),
     (workgroup_width, workgroup_height): (u32, u32),
 ) -> (u32, u32) {
-    let x = (width + workgroup_width - 1) / workgroup_width;
-    let y = (height + workgroup_height - 1) / workgroup_height;
-    return (x, y);
+    let x = width.div_ceil(workgroup_width);
+    let y = height.div_ceil(workgroup_height);
+    (x, y)
 }
 
-pub fn padded_bytes_per_row(width: u32) -> usize {
-    let bytes_per_row = width as usize * 4;
+pub fn padded_bytes_per_row(width: u32, bytes_per_pixel: u32) -> usize {
+    let bytes_per_row = width as usize * bytes_per_pixel as usize;
     let padding = (256 - bytes_per_row % 256) % 256;
     bytes_per_row + padding
 }
+
+/// Strips the row padding required by texture to buffer copies, copying the
+/// first `unpadded_bytes_per_row` bytes of every padded row into `unpadded`.
+pub fn unpad_rows(
+    padded: &[u8],
+    unpadded: &mut [u8],
+    padded_bytes_per_row: usize,
+    unpadded_bytes_per_row: usize,
+) {
+    for (padded, unpadded) in padded
+        .chunks_exact(padded_bytes_per_row)
+        .zip(unpadded.chunks_exact_mut(unpadded_bytes_per_row))
+    {
+        unpadded.copy_from_slice(&padded[..unpadded_bytes_per_row]);
+    }
+}
+
+/// Layout entry for a `texture_2d<f32>` read with `textureLoad`.
+pub fn texture_entry(binding: u32) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Texture {
+            sample_type: TextureSampleType::Float { filterable: false },
+            view_dimension: TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    }
+}
+
+/// Layout entry for a write-only `texture_storage_2d` of `format`.
+pub fn storage_texture_entry(binding: u32, format: TextureFormat) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::StorageTexture {
+            access: StorageTextureAccess::WriteOnly,
+            format,
+            view_dimension: TextureViewDimension::D2,
+        },
+        count: None,
+    }
+}
+
+pub fn uniform_entry(binding: u32) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+pub fn storage_buffer_entry(binding: u32, read_only: bool) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// WGSL name of a storage texture format.
+pub fn storage_format_name(format: TextureFormat) -> Option<&'static str> {
+    match format {
+        TextureFormat::R8Unorm => Some("r8unorm"),
+        TextureFormat::Rg8Unorm => Some("rg8unorm"),
+        TextureFormat::Rgba8Unorm => Some("rgba8unorm"),
+        TextureFormat::Rgba16Float => Some("rgba16float"),
+        TextureFormat::R32Float => Some("r32float"),
+        TextureFormat::Rg32Float => Some("rg32float"),
+        TextureFormat::Rgba32Float => Some("rgba32float"),
+        _ => None,
+    }
+}
+
+/// Substitutes the `OUTPUT_FORMAT` placeholder of a shader with the WGSL name
+/// of `format`, failing if the device cannot write that format.
+pub(crate) fn shader_with_output_format(
+    context: &WgContext,
+    source: &str,
+    format: TextureFormat,
+) -> Result<String> {
+    match storage_format_name(format) {
+        Some(name) if context.supports_storage(format) => Ok(source.replace("OUTPUT_FORMAT", name)),
+        _ => Err(Error::UnsupportedTexture(format!(
+            "{:?} cannot be written by compute shaders on this device",
+            format
+        ))),
+    }
+}
+
+pub(crate) fn check_texture_size(context: &WgContext, width: u32, height: u32) -> Result<()> {
+    let max_dimension = context.device.limits().max_texture_dimension_2d;
+    if width > max_dimension || height > max_dimension {
+        return Err(Error::ExceedsLimits {
+            width,
+            height,
+            max_dimension,
+        });
+    }
+    Ok(())
+}
+
+pub(crate) fn check_same_size(expected: Extent3d, actual: Extent3d) -> Result<()> {
+    if expected.width != actual.width || expected.height != actual.height {
+        return Err(Error::SizeMismatch {
+            expected: (expected.width, expected.height),
+            actual: (actual.width, actual.height),
+        });
+    }
+    Ok(())
+}
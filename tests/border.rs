@@ -0,0 +1,142 @@
+mod common;
+
+use image::{ImageBuffer, Rgba, Rgba32FImage};
+use wgimage::{BorderMode, ConvolutionMethod, Filter, GaussianBlur, WgImageBuffer};
+
+const MODES: [BorderMode; 5] = [
+    BorderMode::Constant([0.2, 0.4, 0.6, 0.8]),
+    BorderMode::Replicate,
+    BorderMode::Reflect,
+    BorderMode::Reflect101,
+    BorderMode::Wrap,
+];
+
+/// Index read for `index` in a row of `len` pixels, `None` for the constant
+/// color.
+fn border_index(index: i32, len: i32, mode: BorderMode) -> Option<i32> {
+    if (0..len).contains(&index) {
+        return Some(index);
+    }
+    match mode {
+        BorderMode::Constant(_) => None,
+        BorderMode::Replicate => Some(index.clamp(0, len - 1)),
+        BorderMode::Reflect => {
+            let k = index.rem_euclid(2 * len);
+            Some(if k >= len { 2 * len - 1 - k } else { k })
+        }
+        BorderMode::Reflect101 if len == 1 => Some(0),
+        BorderMode::Reflect101 => {
+            let k = index.rem_euclid(2 * len - 2);
+            Some(if k >= len { 2 * len - 2 - k } else { k })
+        }
+        BorderMode::Wrap => Some(index.rem_euclid(len)),
+    }
+}
+
+fn gaussian_kernel(sigma: f32) -> Vec<f32> {
+    let radius = (sigma * 3.0).ceil() as i32;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|x| (-0.5 * (x * x) as f32 / (sigma * sigma)).exp())
+        .collect();
+    let sum: f32 = kernel.iter().sum();
+    kernel.iter().map(|value| value / sum).collect()
+}
+
+/// Convolves `image` with `kernel` along columns when `vertical`, along rows
+/// otherwise.
+fn convolve(
+    image: &Rgba32FImage,
+    kernel: &[f32],
+    vertical: bool,
+    mode: BorderMode,
+) -> Rgba32FImage {
+    let radius = kernel.len() as i32 / 2;
+    let (width, height) = image.dimensions();
+    let border_color = match mode {
+        BorderMode::Constant(color) => color,
+        _ => [0.0; 4],
+    };
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let mut sum = [0.0; 4];
+        for (tap, weight) in kernel.iter().enumerate() {
+            let offset = tap as i32 - radius;
+            let color = if vertical {
+                border_index(y as i32 + offset, height as i32, mode)
+                    .map(|y| image.get_pixel(x, y as u32).0)
+            } else {
+                border_index(x as i32 + offset, width as i32, mode)
+                    .map(|x| image.get_pixel(x as u32, y).0)
+            }
+            .unwrap_or(border_color);
+            for (sum, channel) in sum.iter_mut().zip(color) {
+                *sum += weight * channel;
+            }
+        }
+        Rgba(sum)
+    })
+}
+
+fn check_borders(width: u32, height: u32, sigma: f32, method: ConvolutionMethod) {
+    let Some(context) = common::context() else {
+        return;
+    };
+    let image = ImageBuffer::from_fn(width, height, |x, y| {
+        Rgba([
+            x as f32 / width as f32,
+            y as f32 / height as f32,
+            ((x * y) % 5) as f32 / 5.0,
+            1.0,
+        ])
+    });
+    let input = WgImageBuffer::from_host_image(context, image.clone()).unwrap();
+    let kernel = gaussian_kernel(sigma);
+    for mode in MODES {
+        let mut blur = GaussianBlur::new(context, sigma).unwrap();
+        blur.set_method(method);
+        blur.set_border_mode(mode);
+        blur.run(&input).unwrap();
+        let output = blur
+            .output_image()
+            .unwrap()
+            .to_host_image::<Rgba<f32>>(context)
+            .unwrap();
+        let expected = convolve(&convolve(&image, &kernel, true, mode), &kernel, false, mode);
+        for (x, y, pixel) in expected.enumerate_pixels() {
+            for (channel, value) in pixel.0.iter().enumerate() {
+                let actual = output.get_pixel(x, y)[channel];
+                assert!(
+                    (actual - value).abs() < 1e-5,
+                    "{:?} {:?} at ({}, {}): expected {}, got {}",
+                    mode,
+                    method,
+                    x,
+                    y,
+                    value,
+                    actual
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn direct_borders() {
+    check_borders(13, 9, 1.0, ConvolutionMethod::Direct);
+}
+
+#[test]
+fn tiled_borders() {
+    check_borders(13, 9, 3.0, ConvolutionMethod::Tiled);
+}
+
+/// Kernels wider than the image read through several reflections.
+#[test]
+fn borders_wider_than_image() {
+    check_borders(5, 3, 2.0, ConvolutionMethod::Direct);
+    check_borders(5, 3, 2.0, ConvolutionMethod::Tiled);
+}
+
+#[test]
+fn single_pixel_borders() {
+    check_borders(1, 6, 1.0, ConvolutionMethod::Direct);
+}
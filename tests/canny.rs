@@ -0,0 +1,34 @@
+mod common;
+
+use image::{ImageBuffer, Luma, Rgba};
+use wgimage::{Canny, Filter, WgImageBuffer};
+use wgpu::TextureFormat;
+
+/// Single-channel inputs are differentiated on their only channel, like the
+/// same gray image stored as RGBA.
+#[test]
+fn luma_matches_rgba() {
+    let Some(context) = common::context() else {
+        return;
+    };
+    let value = |x: u32| if x < 16 { 0u8 } else { 100 };
+    let luma = ImageBuffer::from_fn(32, 32, |x, _| Luma([value(x)]));
+    let rgba = ImageBuffer::from_fn(32, 32, |x, _| {
+        let v = value(x);
+        Rgba([v, v, v, 255])
+    });
+    let mut edges = Vec::new();
+    for input in [
+        WgImageBuffer::from_host_image(context, luma).unwrap(),
+        WgImageBuffer::from_host_image(context, rgba).unwrap(),
+    ] {
+        let mut canny =
+            Canny::with_format(context, 1.0, 100.0, 200.0, TextureFormat::Rgba8Unorm).unwrap();
+        canny.run(&input).unwrap();
+        let output = canny.output_image().unwrap();
+        edges.push(output.to_host_image::<Rgba<u8>>(context).unwrap());
+    }
+    let count = edges[1].pixels().filter(|pixel| pixel[0] == 255).count();
+    assert!(count > 0);
+    assert_eq!(edges[0], edges[1]);
+}
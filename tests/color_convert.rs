@@ -0,0 +1,200 @@
+mod common;
+
+use image::{ImageBuffer, Rgba, Rgba32FImage};
+use wgimage::{ColorConvert, ColorRange, ColorSpace, Filter, WgImageBuffer, YCbCrMatrix};
+
+const SPACES: [ColorSpace; 11] = [
+    ColorSpace::LinearRgb,
+    ColorSpace::Hsv,
+    ColorSpace::Hsl,
+    ColorSpace::YCbCr(YCbCrMatrix::Bt601, ColorRange::Full),
+    ColorSpace::YCbCr(YCbCrMatrix::Bt601, ColorRange::Limited),
+    ColorSpace::YCbCr(YCbCrMatrix::Bt709, ColorRange::Full),
+    ColorSpace::YCbCr(YCbCrMatrix::Bt709, ColorRange::Limited),
+    ColorSpace::Xyz,
+    ColorSpace::Lab,
+    ColorSpace::Luv,
+    ColorSpace::Rgb,
+];
+
+fn convert(image: &Rgba32FImage, from: ColorSpace, to: ColorSpace) -> Rgba32FImage {
+    let context = common::context().unwrap();
+    let input = WgImageBuffer::from_host_image(context, image.clone()).unwrap();
+    let mut filter = ColorConvert::new(context, from, to).unwrap();
+    filter.run(&input).unwrap();
+    filter
+        .output_image()
+        .unwrap()
+        .to_host_image::<Rgba<f32>>(context)
+        .unwrap()
+}
+
+fn assert_close(actual: &[f32], expected: &[f32], tolerance: f32, what: &str) {
+    for (actual, expected) in actual.iter().zip(expected) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{}: expected {:?}, got {:?}",
+            what,
+            expected,
+            actual
+        );
+    }
+}
+
+/// Every combination of 0, 0.2, ..., 1 per channel, with varying alpha.
+fn color_grid() -> Rgba32FImage {
+    ImageBuffer::from_fn(36, 6, |x, y| {
+        let level = |value: u32| value as f32 / 5.0;
+        Rgba([level(x % 6), level(x / 6), level(y), level(x % 4) / 2.0])
+    })
+}
+
+#[test]
+fn known_values() {
+    if common::context().is_none() {
+        return;
+    }
+    let colors = [
+        [1.0, 0.0, 0.0, 1.0],
+        [0.0, 1.0, 0.0, 1.0],
+        [0.0, 0.0, 1.0, 1.0],
+        [1.0, 1.0, 1.0, 1.0],
+        [1.0, 0.5, 0.0, 0.5],
+    ];
+    let image = ImageBuffer::from_fn(colors.len() as u32, 1, |x, _| Rgba(colors[x as usize]));
+    let expected: [(ColorSpace, [[f32; 3]; 5], f32); 5] = [
+        (
+            ColorSpace::Xyz,
+            [
+                [0.412453, 0.212671, 0.019334],
+                [0.357580, 0.715160, 0.119193],
+                [0.180423, 0.072169, 0.950227],
+                [0.950456, 1.0, 1.088754],
+                [0.488990, 0.365745, 0.044846],
+            ],
+            1e-4,
+        ),
+        (
+            ColorSpace::Lab,
+            [
+                [53.2406, 80.0942, 67.2015],
+                [87.7351, -86.1813, 83.1775],
+                [32.2957, 79.1870, -107.8617],
+                [100.0, 0.0, 0.0],
+                [66.9565, 43.0733, 73.9576],
+            ],
+            2e-2,
+        ),
+        (
+            ColorSpace::Luv,
+            [
+                [53.2406, 175.0148, 37.7521],
+                [87.7351, -83.0774, 107.3923],
+                [32.2957, -9.4047, -130.3395],
+                [100.0, 0.0, 0.0],
+                [66.9565, 106.4548, 61.2999],
+            ],
+            2e-2,
+        ),
+        (
+            ColorSpace::Hsv,
+            [
+                [0.0, 1.0, 1.0],
+                [120.0, 1.0, 1.0],
+                [240.0, 1.0, 1.0],
+                [0.0, 0.0, 1.0],
+                [30.0, 1.0, 1.0],
+            ],
+            1e-4,
+        ),
+        (
+            ColorSpace::Hsl,
+            [
+                [0.0, 1.0, 0.5],
+                [120.0, 1.0, 0.5],
+                [240.0, 1.0, 0.5],
+                [0.0, 0.0, 1.0],
+                [30.0, 1.0, 0.5],
+            ],
+            1e-4,
+        ),
+    ];
+    for (space, values, tolerance) in expected {
+        let output = convert(&image, ColorSpace::Rgb, space);
+        for (x, (pixel, expected)) in output.pixels().zip(values).enumerate() {
+            let what = format!("{:?} of {:?}", space, colors[x]);
+            assert_close(&pixel.0[..3], &expected, tolerance, &what);
+            assert_eq!(pixel[3], colors[x][3], "{}: alpha", what);
+        }
+    }
+}
+
+#[test]
+fn ycbcr_ranges() {
+    if common::context().is_none() {
+        return;
+    }
+    let image = ImageBuffer::from_fn(3, 1, |x, _| {
+        Rgba([x as f32 / 2.0, x as f32 / 2.0, x as f32 / 2.0, 1.0])
+    });
+    let neutral = 128.0 / 255.0;
+    for matrix in [YCbCrMatrix::Bt601, YCbCrMatrix::Bt709] {
+        let full = convert(
+            &image,
+            ColorSpace::Rgb,
+            ColorSpace::YCbCr(matrix, ColorRange::Full),
+        );
+        let limited = convert(
+            &image,
+            ColorSpace::Rgb,
+            ColorSpace::YCbCr(matrix, ColorRange::Limited),
+        );
+        for x in 0..3 {
+            let gray = x as f32 / 2.0;
+            let what = format!("{:?} gray {}", matrix, gray);
+            assert_close(
+                &full.get_pixel(x, 0).0[..3],
+                &[gray, neutral, neutral],
+                1e-5,
+                &what,
+            );
+            assert_close(
+                &limited.get_pixel(x, 0).0[..3],
+                &[(16.0 + 219.0 * gray) / 255.0, neutral, neutral],
+                1e-5,
+                &what,
+            );
+        }
+    }
+}
+
+#[test]
+fn round_trips() {
+    if common::context().is_none() {
+        return;
+    }
+    let image = color_grid();
+    for space in SPACES {
+        let converted = convert(&image, ColorSpace::Rgb, space);
+        let round_trip = convert(&converted, space, ColorSpace::Rgb);
+        for (original, pixel) in image.pixels().zip(round_trip.pixels()) {
+            let what = format!("{:?} round trip of {:?}", space, original.0);
+            assert_close(&pixel.0, &original.0, 1e-4, &what);
+        }
+    }
+}
+
+/// Conversions between two non-RGB spaces go through RGB.
+#[test]
+fn chained_conversions() {
+    if common::context().is_none() {
+        return;
+    }
+    let image = color_grid();
+    let hsv = convert(&image, ColorSpace::Rgb, ColorSpace::Hsv);
+    let direct = convert(&image, ColorSpace::Rgb, ColorSpace::Lab);
+    let chained = convert(&hsv, ColorSpace::Hsv, ColorSpace::Lab);
+    for (direct, chained) in direct.pixels().zip(chained.pixels()) {
+        assert_close(&chained.0, &direct.0, 1e-3, "HSV to Lab");
+    }
+}
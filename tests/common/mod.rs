@@ -0,0 +1,17 @@
+use std::sync::OnceLock;
+
+use futures::executor::block_on;
+use wgimage::{Error, WgContext};
+
+/// Context shared by the tests of a binary, or `None` on machines without an
+/// adapter, in which case the GPU tests are skipped.
+pub fn context() -> Option<&'static WgContext> {
+    static CONTEXT: OnceLock<Option<WgContext>> = OnceLock::new();
+    CONTEXT
+        .get_or_init(|| match block_on(WgContext::new()) {
+            Ok(context) => Some(context),
+            Err(Error::NoAdapter) => None,
+            Err(error) => panic!("failed to create a context: {}", error),
+        })
+        .as_ref()
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use image::{ImageBuffer, Luma, Rgba};
+use wgimage::{Filter, GrayScale, WgImageBuffer};
+use wgpu::TextureFormat;
+
+/// Single-channel images are gray already, so running twice gives the same
+/// levels as running once.
+#[test]
+fn single_channel_is_idempotent() {
+    let Some(context) = common::context() else {
+        return;
+    };
+    let image = ImageBuffer::from_fn(8, 4, |x, y| Rgba([(x * 30) as u8, (y * 60) as u8, 90, 255]));
+    let input = WgImageBuffer::from_host_image(context, image).unwrap();
+    let mut first = GrayScale::with_format(context, TextureFormat::R32Float).unwrap();
+    first.run(&input).unwrap();
+    let gray = first.output_image().unwrap();
+    let mut second = GrayScale::with_format(context, TextureFormat::R32Float).unwrap();
+    second.run(gray).unwrap();
+    let once = gray.to_host_image::<Luma<f32>>(context).unwrap();
+    let twice = second.output_image().unwrap();
+    let twice = twice.to_host_image::<Luma<f32>>(context).unwrap();
+    assert_eq!(once, twice);
+    let expected = (0.299 * 30.0 + 0.587 * 60.0 + 0.114 * 90.0) / 255.0;
+    assert!((once.get_pixel(1, 1)[0] - expected).abs() < 1e-5);
+}
+
+#[test]
+fn luma_input_is_copied() {
+    let Some(context) = common::context() else {
+        return;
+    };
+    let image = ImageBuffer::from_fn(5, 3, |x, y| Luma([(x * 40 + y) as u8]));
+    let input = WgImageBuffer::from_host_image(context, image.clone()).unwrap();
+    let mut grayscale = GrayScale::with_format(context, TextureFormat::Rgba8Unorm).unwrap();
+    grayscale.run(&input).unwrap();
+    let output = grayscale.output_image().unwrap();
+    let readback = output.to_host_image::<Rgba<u8>>(context).unwrap();
+    for (pixel, gray) in readback.pixels().zip(image.pixels()) {
+        assert_eq!(*pixel, Rgba([gray[0], gray[0], gray[0], 255]));
+    }
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use image::{ImageBuffer, Luma, Rgba};
+use wgimage::{Filter, GaussianBlur, WgImageBuffer};
+use wgpu::TextureFormat;
+
+/// Odd widths, single pixels and very tall or wide images, whose rows are
+/// not a multiple of the 256 bytes copies are padded to.
+const SIZES: [(u32, u32); 5] = [(1, 1), (3, 5), (65, 3), (1, 1000), (1366, 4)];
+
+#[test]
+fn rgba8_readback() {
+    let Some(context) = common::context() else {
+        return;
+    };
+    for (width, height) in SIZES {
+        let image = ImageBuffer::from_fn(width, height, |x, y| {
+            Rgba([x as u8, y as u8, (x ^ y) as u8, (x + y) as u8])
+        });
+        let buffer = WgImageBuffer::from_host_image(context, image.clone()).unwrap();
+        let readback = buffer.to_host_image::<Rgba<u8>>(context).unwrap();
+        assert_eq!(readback, image, "{}x{}", width, height);
+    }
+}
+
+#[test]
+fn luma8_readback() {
+    let Some(context) = common::context() else {
+        return;
+    };
+    for (width, height) in SIZES {
+        let image = ImageBuffer::from_fn(width, height, |x, y| Luma([(x * 7 + y * 13) as u8]));
+        let buffer = WgImageBuffer::from_host_image(context, image.clone()).unwrap();
+        let readback = buffer.to_host_image::<Luma<u8>>(context).unwrap();
+        assert_eq!(readback, image, "{}x{}", width, height);
+    }
+}
+
+#[test]
+fn read_into_matches_to_host_image() {
+    let Some(context) = common::context() else {
+        return;
+    };
+    for (width, height) in SIZES {
+        let image = ImageBuffer::from_fn(width, height, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let buffer = WgImageBuffer::from_host_image(context, image.clone()).unwrap();
+        let mut texels = vec![0; image.as_raw().len()];
+        buffer.read_into(context, &mut texels).unwrap();
+        assert_eq!(&texels, image.as_raw(), "{}x{}", width, height);
+    }
+}
+
+/// `Rgba<f32>` pixels are converted to and from `Rgba16Float`, including HDR
+/// values above 1; these ones are exactly representable.
+#[test]
+fn rgba16float_readback() {
+    let Some(context) = common::context() else {
+        return;
+    };
+    for (width, height) in SIZES {
+        let image = ImageBuffer::from_fn(width, height, |x, y| {
+            Rgba([(x % 64) as f32 / 4.0, -((y % 16) as f32) / 8.0, 0.5, 1.0])
+        });
+        let buffer = WgImageBuffer::from_host_image_with_format(
+            context,
+            image.clone(),
+            TextureFormat::Rgba16Float,
+        )
+        .unwrap();
+        assert_eq!(buffer.format(), TextureFormat::Rgba16Float);
+        let readback = buffer.to_host_image::<Rgba<f32>>(context).unwrap();
+        assert_eq!(readback, image, "{}x{}", width, height);
+
+        let doubled = ImageBuffer::from_fn(width, height, |x, y| {
+            let pixel = image.get_pixel(x, y);
+            Rgba([pixel[0] * 2.0, pixel[1] * 2.0, pixel[2] * 2.0, pixel[3]])
+        });
+        buffer.write_host_image(context, &doubled).unwrap();
+        let readback = buffer.to_host_image::<Rgba<f32>>(context).unwrap();
+        assert_eq!(readback, doubled, "{}x{}", width, height);
+    }
+}
+
+#[test]
+fn rgba16float_filter_output() {
+    let Some(context) = common::context() else {
+        return;
+    };
+    if !context.supports_storage(TextureFormat::Rgba16Float) {
+        return;
+    }
+    let image = ImageBuffer::from_pixel(9, 7, Rgba([3.0f32, 0.25, 0.0, 1.0]));
+    let input = WgImageBuffer::from_host_image(context, image.clone()).unwrap();
+    let mut blur = GaussianBlur::with_format(context, 1.0, TextureFormat::Rgba16Float).unwrap();
+    blur.run(&input).unwrap();
+    let output = blur.output_image().unwrap();
+    assert_eq!(output.format(), TextureFormat::Rgba16Float);
+    let readback = output.to_host_image::<Rgba<f32>>(context).unwrap();
+    for pixel in readback.pixels() {
+        for (actual, expected) in pixel.0.iter().zip(image.get_pixel(0, 0).0) {
+            assert!((actual - expected).abs() < 1e-2, "{:?}", pixel);
+        }
+    }
+}
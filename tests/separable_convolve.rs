@@ -0,0 +1,59 @@
+mod common;
+
+use image::{ImageBuffer, Rgba};
+use wgimage::{ConvolutionMethod, Filter, GaussianBlur, WgImageBuffer};
+
+fn blur(input: &WgImageBuffer, sigma: f32, method: ConvolutionMethod) -> Vec<f32> {
+    let context = common::context().unwrap();
+    let mut blur = GaussianBlur::new(context, sigma).unwrap();
+    blur.set_method(method);
+    blur.run(input).unwrap();
+    blur.output_image()
+        .unwrap()
+        .to_host_image::<Rgba<f32>>(context)
+        .unwrap()
+        .into_raw()
+}
+
+fn check_tiled_matches_direct(width: u32, height: u32, sigmas: &[f32]) {
+    let Some(context) = common::context() else {
+        return;
+    };
+    let image = ImageBuffer::from_fn(width, height, |x, y| {
+        Rgba([
+            ((x * 7 + y * 3) % 256) as f32 / 255.0,
+            ((x * y) % 251) as f32 / 251.0,
+            (x % 2) as f32,
+            1.0,
+        ])
+    });
+    let input = WgImageBuffer::from_host_image(context, image).unwrap();
+    for &sigma in sigmas {
+        let direct = blur(&input, sigma, ConvolutionMethod::Direct);
+        let tiled = blur(&input, sigma, ConvolutionMethod::Tiled);
+        let max_difference = direct
+            .iter()
+            .zip(&tiled)
+            .map(|(direct, tiled)| (direct - tiled).abs())
+            .fold(0.0, f32::max);
+        assert!(
+            max_difference < 1e-6,
+            "sigma {}: tiled output differs by {}",
+            sigma,
+            max_difference
+        );
+    }
+}
+
+/// 7 taps, below the size `Auto` tiles from, then 13 and 121 taps.
+#[test]
+fn tiled_matches_direct() {
+    check_tiled_matches_direct(67, 45, &[1.0, 2.0, 20.0]);
+}
+
+/// 901 taps do not fit the largest tile, so `Tiled` falls back to the direct
+/// shader.
+#[test]
+fn tiled_falls_back_for_huge_kernels() {
+    check_tiled_matches_direct(40, 20, &[150.0]);
+}
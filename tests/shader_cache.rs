@@ -0,0 +1,25 @@
+mod common;
+
+use image::{ImageBuffer, Rgba};
+use wgimage::{Filter, GaussianBlur, WgImageBuffer};
+
+#[test]
+fn second_blur_reuses_pipeline() {
+    let Some(context) = common::context() else {
+        return;
+    };
+    let image = ImageBuffer::from_pixel(8, 8, Rgba([255u8, 0, 0, 255]));
+    let input = WgImageBuffer::from_host_image(context, image).unwrap();
+    let mut blur = GaussianBlur::new(context, 1.0).unwrap();
+    blur.run(&input).unwrap();
+    let before = context.shader_cache().stats();
+
+    // Both kernels are small enough for the direct shader.
+    let mut blur = GaussianBlur::new(context, 1.5).unwrap();
+    blur.run(&input).unwrap();
+    let after = context.shader_cache().stats();
+
+    assert_eq!(after.pipeline_hits, before.pipeline_hits + 1);
+    assert_eq!(after.pipeline_misses, before.pipeline_misses);
+    assert_eq!(after.module_misses, before.module_misses);
+}